@@ -0,0 +1,102 @@
+//! Connection churn over loopback with a single accept loop and with one `SO_REUSEPORT`
+//! acceptor per worker thread.  Each connection sends one request and is closed by the
+//! server, so the accept loop is on the hot path.
+//!
+//! Run with `cargo bench --bench accept`.
+
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use {
+    criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput},
+    grafton_server::{axum::routing::get, Builder, Config, GraftonRouter, ListenerConfig},
+    tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+        runtime::Runtime,
+    },
+};
+
+const CLIENTS: usize = 32;
+const CONNECTIONS_PER_CLIENT: usize = 8;
+const REQUEST: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+fn start_server(runtime: &Runtime, reuse_port: bool) -> (grafton_server::ServerHandle, SocketAddr) {
+    let mut config = Config::default();
+    config.website.shutdown_grace_period_secs = 1;
+    config.website.listeners = vec![ListenerConfig {
+        port: 0,
+        reuse_port,
+        ..Default::default()
+    }];
+
+    runtime.block_on(async {
+        let server = Builder::new(config)
+            .with_router(|_| GraftonRouter::new().route("/", get(|| async { "ok" })))
+            .build()
+            .unwrap();
+        let handle = server.start().unwrap();
+        let addr = handle.listeners()[0].local_addr.socket_addr().unwrap();
+        (handle, addr)
+    })
+}
+
+async fn request(addr: SocketAddr) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(REQUEST).await.unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    assert!(response.ends_with(b"ok"));
+}
+
+/// Open `CLIENTS * CONNECTIONS_PER_CLIENT` connections from concurrent clients.
+async fn churn(addr: SocketAddr) {
+    let clients: Vec<_> = (0..CLIENTS)
+        .map(|_| {
+            tokio::spawn(async move {
+                for _ in 0..CONNECTIONS_PER_CLIENT {
+                    request(addr).await;
+                }
+            })
+        })
+        .collect();
+    for client in clients {
+        client.await.unwrap();
+    }
+}
+
+pub fn bench_accept(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("accept");
+    group
+        .throughput(Throughput::Elements(
+            (CLIENTS * CONNECTIONS_PER_CLIENT) as u64,
+        ))
+        .sample_size(20)
+        .measurement_time(Duration::from_secs(10));
+
+    for (name, reuse_port) in [("single", false), ("reuse_port", true)] {
+        let (handle, addr) = start_server(&runtime, reuse_port);
+        group.bench_with_input(BenchmarkId::from_parameter(name), &addr, |b, &addr| {
+            b.iter_custom(|iters| {
+                runtime.block_on(async {
+                    let start = Instant::now();
+                    for _ in 0..iters {
+                        churn(addr).await;
+                    }
+                    start.elapsed()
+                })
+            });
+        });
+
+        handle.shutdown();
+        runtime.block_on(handle.wait()).unwrap();
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_accept);
+criterion_main!(benches);
@@ -1,6 +1,7 @@
 use grafton_config::load_config_from_dir;
-use grafton_server::{add, axum::Router, GraftonRouter, Builder, Context, Error, Logger};
-use tokio::signal;
+use grafton_server::{
+    add, axum::Router, shutdown_signal, Builder, Context, Error, GraftonRouter, Logger,
+};
 use tracing::info;
 
 use std::sync::Arc;
@@ -58,10 +59,11 @@ async fn main() -> Result<(), Error> {
 
     let server = builder.with_router(build_todos_router).build()?;
 
-    server.start();
+    let handle = server.start()?;
     info!("Server started successfully");
 
-    signal::ctrl_c().await?;
+    handle.shutdown_on(shutdown_signal());
+    handle.wait().await?;
     info!("Server shutdown gracefully");
 
     Ok(())
@@ -1,6 +1,9 @@
 use std::sync::Arc;
 
-use crate::{model::Context, tracing::debug, Error, GraftonRouter, RouterFactory, ServerConfigProvider};
+use crate::{
+    model::Context, tracing::debug, Error, GraftonRouter, OcspFetcher, RouterFactory,
+    ServerConfigProvider,
+};
 
 use super::server::Server;
 
@@ -10,6 +13,7 @@ where
 {
     app_ctx: Arc<Context<C>>,
     router_factory: Option<Box<RouterFactory<C>>>,
+    ocsp_fetcher: Option<Arc<dyn OcspFetcher>>,
 }
 
 impl<C> Builder<C>
@@ -29,6 +33,7 @@ where
         Self {
             app_ctx: context,
             router_factory: None,
+            ocsp_fetcher: None,
         }
     }
 
@@ -41,6 +46,17 @@ where
         self
     }
 
+    /// Staple OCSP responses obtained from `fetcher`, instead of from the responder
+    /// named in each certificate when `ocsp_fetch` is set.
+    #[must_use]
+    pub fn with_ocsp_fetcher<F>(mut self, fetcher: F) -> Self
+    where
+        F: OcspFetcher + 'static,
+    {
+        self.ocsp_fetcher = Some(Arc::new(fetcher));
+        self
+    }
+
     /// Build the server.
     ///
     /// # Errors
@@ -57,6 +73,8 @@ where
         Ok(Server {
             router: router.with_state(app_ctx.clone()),
             config: app_ctx.config.clone(),
+            context: app_ctx,
+            ocsp_fetcher: self.ocsp_fetcher,
         })
     }
 }
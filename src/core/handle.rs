@@ -0,0 +1,183 @@
+use std::{future::Future, sync::Arc};
+
+use {
+    tokio::{signal, task::JoinHandle},
+    tokio_util::sync::CancellationToken,
+};
+
+use crate::{
+    tracing::{debug, error, info},
+    util::{stats::ServerStats, tls::CertResolver},
+    CertificateInfo, Error, ListenAddr,
+};
+
+/// A socket the server is listening on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoundListener {
+    pub name: Option<String>,
+    pub local_addr: ListenAddr,
+    pub tls: bool,
+}
+
+/// A handle to a running [`Server`](crate::Server).
+///
+/// Dropping the handle does not stop the server.  Call [`ServerHandle::shutdown`],
+/// cancel the token returned by [`ServerHandle::shutdown_token`] or register a
+/// signal with [`ServerHandle::shutdown_on`], then await [`ServerHandle::wait`] to
+/// let in-flight requests drain.
+#[derive(Debug)]
+pub struct ServerHandle {
+    shutdown: CancellationToken,
+    tasks: Vec<JoinHandle<Result<(), Error>>>,
+    listeners: Vec<BoundListener>,
+    cert_resolver: Option<Arc<CertResolver>>,
+    stats: Arc<ServerStats>,
+}
+
+impl ServerHandle {
+    pub(crate) const fn new(
+        shutdown: CancellationToken,
+        tasks: Vec<JoinHandle<Result<(), Error>>>,
+        listeners: Vec<BoundListener>,
+        cert_resolver: Option<Arc<CertResolver>>,
+        stats: Arc<ServerStats>,
+    ) -> Self {
+        Self {
+            shutdown,
+            tasks,
+            listeners,
+            cert_resolver,
+            stats,
+        }
+    }
+
+    /// Reload the TLS certificate and key from disk for new handshakes.  Does nothing when
+    /// no listener uses TLS.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, and keeps the current certificate, if the new files are unusable.
+    pub fn reload_tls(&self) -> Result<(), Error> {
+        self.cert_resolver.as_ref().map_or_else(
+            || {
+                debug!("TLS reload requested but no listener uses TLS");
+                Ok(())
+            },
+            |resolver| resolver.reload(),
+        )
+    }
+
+    /// Details of the loaded TLS certificates, including the days until each expires.
+    /// Empty when no listener uses TLS.
+    #[must_use]
+    pub fn certificates(&self) -> Vec<CertificateInfo> {
+        self.cert_resolver
+            .as_ref()
+            .map(|resolver| resolver.certificates())
+            .unwrap_or_default()
+    }
+
+    /// The sockets which were bound when the server started.
+    #[must_use]
+    pub fn listeners(&self) -> &[BoundListener] {
+        &self.listeners
+    }
+
+    /// Connection counters shared by every listener, which keep updating while the server
+    /// runs.
+    #[must_use]
+    pub fn stats(&self) -> Arc<ServerStats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// Stop accepting new connections and begin draining the open ones.
+    pub fn shutdown(&self) {
+        debug!("Server shutdown requested");
+        self.shutdown.cancel();
+    }
+
+    /// A token which triggers a graceful shutdown when cancelled.
+    #[must_use]
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Trigger a graceful shutdown once `signal` completes.
+    pub fn shutdown_on<F>(&self, signal: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let token = self.shutdown.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                () = signal => {
+                    info!("Shutdown signal received");
+                    token.cancel();
+                }
+                () = token.cancelled() => {}
+            }
+        });
+    }
+
+    #[must_use]
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.is_cancelled()
+    }
+
+    /// Wait for every listener to stop and its connections to drain.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error reported by a listener, or an error if a listener task panicked.
+    pub async fn wait(self) -> Result<(), Error> {
+        let mut result = Ok(());
+
+        for task in self.tasks {
+            let outcome = match task.await {
+                Ok(outcome) => outcome,
+                Err(e) => Err(e.into()),
+            };
+
+            if let Err(e) = outcome {
+                error!("Listener terminated with an error: {}", e);
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+
+        info!("Server shutdown complete");
+        result
+    }
+}
+
+/// Completes when the process receives Ctrl+C, or SIGTERM on unix platforms.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {},
+        () = terminate => {},
+    }
+}
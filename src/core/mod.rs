@@ -1,4 +1,5 @@
 pub mod builder;
+pub mod handle;
 pub mod server;
 
 /// Adds two numbers together.  A trivial example of a public function.
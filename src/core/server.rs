@@ -1,72 +1,551 @@
-use std::{net::IpAddr, str::FromStr as _, sync::Arc};
+use std::{future::Future, iter, path::Path, sync::Arc, time::Duration};
+
+use {
+    tokio::{net::TcpListener, task::JoinHandle},
+    tokio_rustls::{rustls::ServerConfig, TlsAcceptor},
+    tokio_util::sync::CancellationToken,
+};
+
+#[cfg(feature = "http3")]
+use {
+    crate::util::http3::{self, serve_http3, with_alt_svc},
+    std::net::SocketAddr,
+};
 
 use crate::{
-    axum::Router,
-    tracing::{debug, error},
-    util::http::{serve_http, serve_https},
-    ServerConfigProvider, SslConfig,
+    axum::{Extension, Router},
+    model::Context,
+    tracing::{debug, error, info, warn},
+    util::{
+        acme::{http01_router, AcmeManager, Http01Tokens},
+        certificate::spawn_expiry_task,
+        client_ip::TrustedProxies,
+        http::{https_redirect_router, serve, with_hsts, ConnectionSettings},
+        limits::ConnectionLimiter,
+        listener::{self, BoundSocket, InheritedSockets, ListenAddr, TcpSocketListener},
+        ocsp::{spawn_ocsp_task, HttpOcspFetcher, OcspFetcher},
+        self_signed::ensure_self_signed,
+        stats::ServerStats,
+        tls::{create_tls_config, spawn_reload_task, CertResolver},
+        Website,
+    },
+    AcmeChallenge, Error, HttpMode, HttpProtocol, ListenerConfig, ServerConfigProvider, SslConfig,
+    TimeoutConfig,
 };
 
+use super::handle::{BoundListener, ServerHandle};
+
 pub struct Server<C>
 where
     C: ServerConfigProvider,
 {
     pub router: Router,
     pub config: Arc<C>,
+    pub(crate) context: Arc<Context<C>>,
+    pub(crate) ocsp_fetcher: Option<Arc<dyn OcspFetcher>>,
+}
+
+struct PreparedListener {
+    config: ListenerConfig,
+    socket: BoundSocket,
+    local_addr: ListenAddr,
+    /// Extra `SO_REUSEPORT` sockets sharing the address of `socket`.
+    reuse_port_sockets: Vec<TcpListener>,
+    acceptor: Option<TlsAcceptor>,
+    router: Router,
+    /// The QUIC endpoint serving HTTP/3 and its local address.
+    #[cfg(feature = "http3")]
+    quic: Option<(quinn::Endpoint, SocketAddr)>,
+}
+
+impl PreparedListener {
+    /// Pair a bound socket with the acceptor and router for its kind of listener, binding
+    /// its QUIC endpoint when it serves HTTP/3.  Everything that can fail happens here, so
+    /// that the listener can be spawned without errors.
+    fn new(
+        config: ListenerConfig,
+        socket: BoundSocket,
+        reuse_port_sockets: Vec<TcpListener>,
+        tls: Option<&Tls>,
+        tls_router: &Router,
+        plain_router: &Router,
+    ) -> Result<Self, Error> {
+        let local_addr = socket.local_addr()?;
+        let (acceptor, router) = if config.tls {
+            let acceptor = tls.map(|tls| tls_acceptor(&tls.config, &config.protocol));
+            (acceptor, tls_router.clone())
+        } else {
+            (None, plain_router.clone())
+        };
+
+        #[cfg(feature = "http3")]
+        let (quic, router) = if config.http3 {
+            let endpoint = bind_http3(&config, &socket, tls.map(|tls| &*tls.config))?;
+            let quic_addr = endpoint.local_addr()?;
+            (
+                Some((endpoint, quic_addr)),
+                with_alt_svc(router, quic_addr.port()),
+            )
+        } else {
+            (None, router)
+        };
+        #[cfg(not(feature = "http3"))]
+        if config.http3 {
+            return Err(Error::InvalidListenerConfig {
+                listener: config.display_name(),
+                cause: "HTTP/3 requires the http3 feature".into(),
+            });
+        }
+
+        Ok(Self {
+            config,
+            socket,
+            local_addr,
+            reuse_port_sockets,
+            acceptor,
+            router,
+            #[cfg(feature = "http3")]
+            quic,
+        })
+    }
 }
 
 impl<C> Server<C>
 where
     C: ServerConfigProvider,
 {
-    pub fn start(self) {
-        let server_config = self.config.get_server_config();
-        let bind_address_str = server_config.website.bind_address.to_string();
-        let ports = server_config.website.bind_ports.clone();
-        let ssl_config = server_config.website.bind_ssl_config.clone();
-
-        if ssl_config.enabled {
-            self.start_https_server(&bind_address_str, ports.https, ssl_config);
+    /// Bind every configured listener and start serving, returning a handle which can be
+    /// used to shut the server down.
+    ///
+    /// Listeners come from `website.listeners`, or from the single-address settings when
+    /// that list is empty.  While any TLS listener exists, plain listeners redirect to the
+    /// public HTTPS URL if `website.http_mode` is `redirect`.  Must be called from within a
+    /// tokio runtime.
+    ///
+    /// The bound sockets are available from the returned handle and from the [`Context`]
+    /// before any request is served.  A public port of 0 is replaced by the port the OS
+    /// picked for the first listener of the same kind, in the context's website config and
+    /// in HTTPS redirects.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, without starting any listener, if the TLS configuration cannot be
+    /// loaded or a socket cannot be bound.
+    pub fn start(self) -> Result<ServerHandle, Error> {
+        let website = &self.config.get_server_config().website;
+        let grace_period = Duration::from_secs(website.shutdown_grace_period_secs);
+        let listener_configs = website.effective_listeners();
+
+        let tls = if listener_configs.iter().any(|l| l.tls) {
+            Some(Tls::load(website)?)
         } else {
-            self.start_http_server(&bind_address_str, ports.http);
+            if website.acme.enabled {
+                warn!("ACME is enabled but no listener uses TLS");
+            }
+            None
+        };
+
+        let mut inherited = if listener_configs.iter().any(|l| l.inherit_fd.is_some()) {
+            InheritedSockets::from_env()
+        } else {
+            InheritedSockets::empty()
+        };
+
+        let mut sockets = Vec::with_capacity(listener_configs.len());
+        let mut bound = Vec::with_capacity(listener_configs.len());
+        for config in listener_configs {
+            let socket = listener::bind(&config, &mut inherited)?;
+            let reuse_port_sockets = listener::bind_acceptors(&config, &socket)?;
+            bound.push(BoundListener {
+                name: config.name.clone(),
+                local_addr: socket.local_addr()?,
+                tls: config.tls,
+            });
+            sockets.push((config, socket, reuse_port_sockets));
         }
 
-        debug!("Server startup initiated");
-    }
+        let website = &resolve_public_ports(website, &bound);
+        self.context.set_started(bound.clone(), website.clone());
+
+        let (tls_router, plain_router) = self.routers(website, tls.as_ref(), &bound);
+
+        let mut prepared = Vec::with_capacity(sockets.len());
+        for (config, socket, reuse_port_sockets) in sockets {
+            prepared.push(PreparedListener::new(
+                config,
+                socket,
+                reuse_port_sockets,
+                tls.as_ref(),
+                &tls_router,
+                &plain_router,
+            )?);
+        }
+
+        let shutdown = CancellationToken::new();
+        let cert_resolver = tls.map(|tls| tls.spawn_tasks(self.ocsp_fetcher.clone(), &shutdown));
 
-    fn start_https_server(&self, addr: &str, port: u16, ssl_config: SslConfig) {
-        let https_addr = match IpAddr::from_str(addr) {
-            Ok(ip) => (ip, port).into(),
-            Err(e) => {
-                error!("Invalid IP address: {}", e);
-                return;
+        let stats = Arc::new(ServerStats::default());
+        let limiter = Arc::new(ConnectionLimiter::new(
+            website.connection_limits.clone(),
+            Arc::clone(&stats),
+        ));
+        let mut tasks = Vec::with_capacity(prepared.len());
+
+        #[cfg_attr(not(feature = "http3"), allow(unused_mut))]
+        for mut listener in prepared {
+            #[cfg(feature = "http3")]
+            if let Some((endpoint, quic_addr)) = listener.quic.take() {
+                tasks.push(spawn_http3(
+                    endpoint,
+                    quic_addr,
+                    &listener,
+                    shutdown.clone(),
+                    grace_period,
+                ));
+            }
+
+            info!(
+                "{} listener {} bound to {}",
+                if listener.config.tls { "HTTPS" } else { "HTTP" },
+                listener.config.display_name(),
+                listener.local_addr
+            );
+            if !listener.reuse_port_sockets.is_empty() {
+                info!(
+                    "Listener {} accepting on {} SO_REUSEPORT sockets",
+                    listener.config.display_name(),
+                    listener.reuse_port_sockets.len() + 1
+                );
             }
+
+            tasks.extend(Self::spawn_listener(
+                listener,
+                &website.timeouts,
+                &limiter,
+                &stats,
+                shutdown.clone(),
+                grace_period,
+            ));
+        }
+
+        debug!("Server startup complete");
+
+        Ok(ServerHandle::new(
+            shutdown,
+            tasks,
+            bound,
+            cert_resolver,
+            stats,
+        ))
+    }
+
+    /// The routers for TLS and plain listeners.  Plain listeners redirect to HTTPS when
+    /// configured to, and answer ACME http-01 challenges.
+    fn routers(
+        &self,
+        website: &Website,
+        tls: Option<&Tls>,
+        bound: &[BoundListener],
+    ) -> (Router, Router) {
+        let router = self.router.clone().layer(Extension(TrustedProxies::new(
+            website.trusted_proxies.clone(),
+        )));
+
+        let tls_router = if website.hsts.enabled {
+            with_hsts(router.clone(), &website.hsts)
+        } else {
+            router.clone()
         };
 
-        let https_router = self.router.clone();
+        let plain_router = if tls.is_some() && website.http_mode == HttpMode::Redirect {
+            https_redirect_router(&website.public_https_url(), &website.redirect_status)
+        } else {
+            router
+        };
 
-        tokio::spawn(async move {
-            if let Err(e) = serve_https(https_addr, https_router, ssl_config).await {
-                error!("Failed to start HTTPS server: {}", e);
+        let plain_router = match tls.and_then(|tls| tls.acme.as_ref()) {
+            Some(acme) if website.acme.challenge == AcmeChallenge::Http01 => {
+                if bound.iter().all(|l| l.tls) {
+                    warn!("ACME http-01 challenges need a plain HTTP listener on port 80");
+                }
+                http01_router(acme.http01_tokens()).merge(plain_router)
             }
-        });
+            _ => plain_router,
+        };
+
+        (tls_router, plain_router)
+    }
+
+    fn spawn_listener(
+        prepared: PreparedListener,
+        timeouts: &TimeoutConfig,
+        limiter: &Arc<ConnectionLimiter>,
+        stats: &Arc<ServerStats>,
+        shutdown: CancellationToken,
+        grace_period: Duration,
+    ) -> Vec<JoinHandle<Result<(), Error>>> {
+        let PreparedListener {
+            config,
+            socket,
+            reuse_port_sockets,
+            acceptor,
+            router,
+            ..
+        } = prepared;
+
+        let settings = Arc::new(ConnectionSettings::new(
+            &config,
+            timeouts,
+            Arc::clone(limiter),
+            Arc::clone(stats),
+        ));
+
+        let name = config.display_name();
+        match socket {
+            BoundSocket::Tcp(listener) => iter::once(listener)
+                .chain(reuse_port_sockets)
+                .map(|listener| {
+                    let listener = TcpSocketListener::new(listener, &config.tcp);
+                    spawn_accept_loop(
+                        name.clone(),
+                        serve(
+                            listener,
+                            acceptor.clone(),
+                            settings.clone(),
+                            router.clone(),
+                            shutdown.clone(),
+                            grace_period,
+                        ),
+                    )
+                })
+                .collect(),
+            #[cfg(unix)]
+            BoundSocket::Unix(listener) => vec![spawn_accept_loop(
+                name,
+                serve(listener, acceptor, settings, router, shutdown, grace_period),
+            )],
+        }
     }
+}
+
+/// The certificates and TLS config shared by every TLS listener.
+struct Tls {
+    ssl_config: SslConfig,
+    cert_resolver: Arc<CertResolver>,
+    acme: Option<AcmeManager>,
+    config: Arc<ServerConfig>,
+}
 
-    fn start_http_server(&self, addr: &str, port: u16) {
-        let http_addr = match IpAddr::from_str(addr) {
-            Ok(ip) => (ip, port).into(),
-            Err(e) => {
-                error!("Invalid IP address: {}", e);
-                return;
+impl Tls {
+    fn load(website: &Website) -> Result<Self, Error> {
+        let ssl_config = if website.acme.enabled {
+            SslConfig {
+                cert_path: website.acme.cert_path().to_string_lossy().into_owned(),
+                key_path: website.acme.key_path().to_string_lossy().into_owned(),
+                generate_self_signed: false,
+                ..website.bind_ssl_config.clone()
             }
+        } else {
+            website.bind_ssl_config.clone()
         };
 
-        let http_router = self.router.clone();
+        if ssl_config.generate_self_signed {
+            ensure_self_signed(&ssl_config, &website.public_hostname)?;
+        }
 
-        tokio::spawn(async move {
-            if let Err(e) = serve_http(http_addr, http_router).await {
-                error!("Failed to start HTTP server: {}", e);
+        let (cert_resolver, acme) = create_cert_resolver(website, &ssl_config)?;
+        if let Some(default) = cert_resolver.certificates().into_iter().next() {
+            if !default.covers(&website.public_hostname) {
+                warn!(
+                    "Default TLS certificate for {:?} does not cover public_hostname {}",
+                    default.server_names, website.public_hostname
+                );
             }
+        }
+        let config = Arc::new(create_tls_config(&ssl_config, cert_resolver.clone())?);
+
+        Ok(Self {
+            ssl_config,
+            cert_resolver,
+            acme,
+            config,
+        })
+    }
+
+    /// Start reloading the certificates, warning before they expire, stapling OCSP
+    /// responses and renewing the certificates over ACME until `shutdown`, returning the
+    /// resolver for the server handle.
+    fn spawn_tasks(
+        self,
+        ocsp_fetcher: Option<Arc<dyn OcspFetcher>>,
+        shutdown: &CancellationToken,
+    ) -> Arc<CertResolver> {
+        spawn_reload_task(self.cert_resolver.clone(), shutdown.clone());
+        spawn_expiry_task(
+            self.cert_resolver.clone(),
+            Duration::from_secs(self.ssl_config.expiry_check_interval_secs),
+            self.ssl_config.expiry_warning_days.clone(),
+            shutdown.clone(),
+        );
+
+        let ocsp_fetcher = ocsp_fetcher.or_else(|| {
+            self.ssl_config
+                .ocsp_fetch
+                .then(|| Arc::new(HttpOcspFetcher::new()) as Arc<dyn OcspFetcher>)
+        });
+        if ocsp_fetcher.is_some() || self.ssl_config.ocsp_response_path.is_some() {
+            spawn_ocsp_task(
+                self.cert_resolver.clone(),
+                &self.ssl_config,
+                ocsp_fetcher,
+                shutdown.clone(),
+            );
+        }
+
+        if let Some(acme) = self.acme {
+            acme.spawn(shutdown.clone());
+        }
+
+        self.cert_resolver
+    }
+}
+
+/// Run an accept loop on its own task, logging the error which stops it.
+fn spawn_accept_loop(
+    name: String,
+    serve: impl Future<Output = Result<(), Error>> + Send + 'static,
+) -> JoinHandle<Result<(), Error>> {
+    tokio::spawn(async move {
+        serve
+            .await
+            .inspect_err(|e| error!("Listener {} failed: {}", name, e))
+    })
+}
+
+/// The website config with any public port of 0 replaced by the port of the first bound
+/// TCP listener of the same kind, so public URLs point at sockets bound to ephemeral
+/// ports.
+fn resolve_public_ports(website: &Website, bound: &[BoundListener]) -> Website {
+    let bound_port = |tls| {
+        bound
+            .iter()
+            .filter(|listener| listener.tls == tls)
+            .find_map(|listener| listener.local_addr.socket_addr())
+            .map(|addr| addr.port())
+    };
+
+    let mut website = website.clone();
+    if website.public_ports.http == 0 {
+        if let Some(port) = bound_port(false) {
+            website.public_ports.http = port;
+        }
+    }
+    if website.public_ports.https == 0 {
+        if let Some(port) = bound_port(true) {
+            website.public_ports.https = port;
+        }
+    }
+    website
+}
+
+/// A TLS acceptor which only negotiates the ALPN protocols of the HTTP versions the listener
+/// serves.
+fn tls_acceptor(tls_config: &Arc<ServerConfig>, protocol: &HttpProtocol) -> TlsAcceptor {
+    if *protocol == HttpProtocol::Auto {
+        return TlsAcceptor::from(Arc::clone(tls_config));
+    }
+
+    let mut tls_config = ServerConfig::clone(tls_config);
+    tls_config
+        .alpn_protocols
+        .retain(|protocol_id| protocol.allows_alpn(protocol_id));
+    TlsAcceptor::from(Arc::new(tls_config))
+}
+
+/// Serve HTTP/3 on the QUIC endpoint of `listener` on its own task.
+#[cfg(feature = "http3")]
+fn spawn_http3(
+    endpoint: quinn::Endpoint,
+    local_addr: SocketAddr,
+    listener: &PreparedListener,
+    shutdown: CancellationToken,
+    grace_period: Duration,
+) -> JoinHandle<Result<(), Error>> {
+    let name = listener.config.display_name();
+    info!("HTTP/3 listener {} bound to udp {}", name, local_addr);
+
+    let serve = serve_http3(endpoint, listener.router.clone(), shutdown, grace_period);
+    tokio::spawn(async move {
+        serve
+            .await
+            .inspect_err(|e| error!("HTTP/3 listener {} failed: {}", name, e))
+    })
+}
+
+/// Bind the QUIC endpoint of a TLS listener on the UDP port matching its TCP socket.
+#[cfg(feature = "http3")]
+fn bind_http3(
+    config: &ListenerConfig,
+    socket: &BoundSocket,
+    tls_config: Option<&ServerConfig>,
+) -> Result<quinn::Endpoint, Error> {
+    let (BoundSocket::Tcp(listener), Some(tls_config)) =
+        (socket, tls_config.filter(|_| config.tls))
+    else {
+        return Err(Error::InvalidListenerConfig {
+            listener: config.display_name(),
+            cause: "HTTP/3 requires a TCP listener with tls enabled".into(),
         });
+    };
+
+    http3::bind_endpoint(listener.local_addr()?, config.ipv6_only, tls_config)
+}
+
+/// Load the TLS certificates, starting from a temporary self-signed certificate when ACME
+/// has not yet provided one.
+fn create_cert_resolver(
+    website: &Website,
+    ssl_config: &SslConfig,
+) -> Result<(Arc<CertResolver>, Option<AcmeManager>), Error> {
+    if !website.acme.enabled {
+        return Ok((Arc::new(CertResolver::new(ssl_config)?), None));
     }
+
+    let domains = website.acme_domains();
+    let resolver = if Path::new(&ssl_config.cert_path).exists() {
+        match CertResolver::new(ssl_config) {
+            Err(
+                e @ (Error::InvalidCertificate { .. }
+                | Error::KeyMismatch { .. }
+                | Error::TlsFileError { .. }),
+            ) => {
+                warn!(
+                    "Cached ACME certificate cannot be used, serving a temporary self-signed certificate for {}: {}",
+                    domains[0], e
+                );
+                CertResolver::with_self_signed(ssl_config, &domains[0])
+            }
+            result => result,
+        }
+    } else {
+        info!(
+            "No ACME certificate cached yet, serving a temporary self-signed certificate for {}",
+            domains[0]
+        );
+        CertResolver::with_self_signed(ssl_config, &domains[0])
+    }?;
+
+    let resolver = Arc::new(if website.acme.challenge == AcmeChallenge::TlsAlpn01 {
+        resolver.with_tls_alpn_challenges()
+    } else {
+        resolver
+    });
+    let acme = AcmeManager::new(
+        website.acme.clone(),
+        domains,
+        resolver.clone(),
+        Http01Tokens::default(),
+    );
+
+    Ok((resolver, Some(acme)))
 }
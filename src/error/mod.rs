@@ -1,4 +1,4 @@
-use std::io;
+use std::{io, net::SocketAddr, path::PathBuf};
 
 use {
     crate::axum::{
@@ -7,6 +7,7 @@ use {
         response::{IntoResponse, Response},
     },
     thiserror::Error,
+    tokio::task::JoinError,
     tokio_rustls::rustls::Error as RustlsError,
     url::ParseError,
 };
@@ -22,6 +23,42 @@ pub enum Error {
     #[error("TLS configuration error: {0}")]
     TlsConfigError(#[from] RustlsError),
 
+    #[error("Failed to load TLS file '{path}': {source}")]
+    TlsFileError { path: String, source: io::Error },
+
+    #[error("Invalid SSL configuration: {0}")]
+    InvalidSslConfig(String),
+
+    #[error("Failed to generate certificate: {0}")]
+    CertificateGenerationError(#[from] rcgen::Error),
+
+    #[error("ACME error: {0}")]
+    AcmeError(#[from] instant_acme::Error),
+
+    #[error("ACME order failed: {0}")]
+    AcmeOrderFailed(String),
+
+    #[error("OCSP error: {0}")]
+    OcspError(String),
+
+    #[error("Invalid certificate '{path}': {cause}")]
+    InvalidCertificate { path: String, cause: String },
+
+    #[error("Private key '{key_path}' does not match certificate '{cert_path}'")]
+    KeyMismatch { cert_path: String, key_path: String },
+
+    #[error("Failed to bind {addr}: {source}")]
+    BindError { addr: SocketAddr, source: io::Error },
+
+    #[error("Failed to bind unix socket {path:?}: {source}")]
+    UnixBindError { path: PathBuf, source: io::Error },
+
+    #[error("Failed to adopt inherited socket for listener '{listener}': {source}")]
+    InheritFdError { listener: String, source: io::Error },
+
+    #[error("Invalid listener '{listener}': {cause}")]
+    InvalidListenerConfig { listener: String, cause: String },
+
     #[error("Error formatting URL with protocol '{protocol}', hostname '{hostname}', port {port}, cause {cause}, inner {inner}")]
     UrlFormatError {
         protocol: String,
@@ -31,6 +68,9 @@ pub enum Error {
         cause: String,
     },
 
+    #[error("Server task failed: {0}")]
+    TaskJoinError(#[from] JoinError),
+
     #[error("Missing router factory")]
     MissingRouterFactory,
 }
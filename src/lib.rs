@@ -4,6 +4,8 @@
 
 mod error;
 pub mod model;
+#[cfg(feature = "test-util")]
+pub mod test;
 mod util;
 
 mod core;
@@ -17,11 +19,27 @@ use strum::{EnumString, VariantNames};
 
 pub use {
     axum,
-    core::{builder::Builder, server::Server},
+    core::{
+        builder::Builder,
+        handle::{shutdown_signal, BoundListener, ServerHandle},
+        server::Server,
+    },
     error::Error,
     model::Context,
     tracing,
-    util::{Config, Logger, SslConfig},
+    util::{
+        certificate::CertificateInfo,
+        client_cert::ClientCertificate,
+        client_ip::{ClientIp, TrustedProxies},
+        listener::{ListenAddr, PeerAddr, UnixPeer},
+        ocsp::{HttpOcspFetcher, OcspFetcher, OcspFuture},
+        proxy_protocol::ProxyHeader,
+        stats::ServerStats,
+        AcmeChallenge, AcmeConfig, CertificateConfig, ClientAuth, Config, ConnectionLimitAction,
+        ConnectionLimitConfig, HstsConfig, Http1Config, Http2Config, HttpMode, HttpProtocol,
+        InheritFd, ListenerConfig, ListenerKind, Logger, RedirectStatus, SslConfig,
+        TcpSocketConfig, TimeoutConfig, TlsVersion, Website,
+    },
 };
 
 pub type GraftonRouter<C> = crate::axum::Router<Arc<Context<C>>>;
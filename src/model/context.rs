@@ -1,9 +1,12 @@
 use std::{
     fmt::{Debug, Formatter},
-    sync::Arc,
+    sync::{Arc, OnceLock},
 };
 
-use crate::{axum::extract::FromRef, Config, ServerConfigProvider};
+use crate::{
+    axum::extract::FromRef, tracing::warn, util::Website, BoundListener, Config,
+    ServerConfigProvider,
+};
 
 #[derive(Clone)]
 pub struct Context<C>
@@ -11,6 +14,14 @@ where
     C: ServerConfigProvider,
 {
     pub config: Arc<C>,
+    started: Arc<OnceLock<Started>>,
+}
+
+/// What the server resolved when it bound its listeners.
+#[derive(Debug)]
+struct Started {
+    listeners: Vec<BoundListener>,
+    website: Website,
 }
 
 impl<C> Debug for Context<C>
@@ -20,6 +31,7 @@ where
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         f.debug_struct("Context")
             .field("config", &self.config)
+            .field("started", &self.started.get())
             .finish()
     }
 }
@@ -32,6 +44,33 @@ where
     pub fn new(config: C) -> Self {
         Self {
             config: Arc::new(config),
+            started: Arc::default(),
+        }
+    }
+
+    /// The sockets the server bound, including the ports the OS picked for listeners
+    /// configured with port 0.  Empty until the server has started.
+    #[must_use]
+    pub fn listeners(&self) -> &[BoundListener] {
+        self.started
+            .get()
+            .map_or(&[], |started| started.listeners.as_slice())
+    }
+
+    /// The website config, with any public port of 0 replaced by the port the server
+    /// bound once it has started, so that `public_server_url` points at the running
+    /// server.
+    #[must_use]
+    pub fn website(&self) -> &Website {
+        self.started.get().map_or_else(
+            || &self.config.get_server_config().website,
+            |started| &started.website,
+        )
+    }
+
+    pub(crate) fn set_started(&self, listeners: Vec<BoundListener>, website: Website) {
+        if self.started.set(Started { listeners, website }).is_err() {
+            warn!("Server context was already started, keeping the first bound listeners");
         }
     }
 }
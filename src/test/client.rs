@@ -0,0 +1,459 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Debug, Formatter},
+    future::{Future, IntoFuture},
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use {
+    bytes::Bytes,
+    http_body_util::{BodyExt, Full},
+    hyper_rustls::{HttpsConnector, HttpsConnectorBuilder},
+    hyper_util::{
+        client::legacy::{connect::HttpConnector, Client},
+        rt::TokioExecutor,
+    },
+    rustls_pki_types::CertificateDer,
+    serde::{de::DeserializeOwned, Serialize},
+    tokio_rustls::rustls::{ClientConfig, RootCertStore},
+};
+
+use crate::{
+    axum::http::{
+        header::{CONTENT_TYPE, COOKIE, SET_COOKIE},
+        HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode,
+    },
+    util::tls::crypto_provider,
+    Error,
+};
+
+/// Sends requests to a [`TestServer`](super::TestServer), keeping the cookies its
+/// responses set like a browser would.  Clones share the same cookies.
+#[derive(Clone)]
+pub struct TestClient {
+    base_url: Arc<str>,
+    client: Client<HttpsConnector<HttpConnector>, Full<Bytes>>,
+    cookies: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+impl Debug for TestClient {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TestClient")
+            .field("base_url", &self.base_url)
+            .field("cookies", &self.cookies)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TestClient {
+    /// A client for the server at `base_url`, trusting the certificates in `roots` for
+    /// HTTPS.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the TLS client configuration cannot be built.
+    pub fn new(
+        base_url: impl Into<String>,
+        roots: Vec<CertificateDer<'static>>,
+    ) -> Result<Self, Error> {
+        let mut root_store = RootCertStore::empty();
+        root_store.add_parsable_certificates(roots);
+        let tls = ClientConfig::builder_with_provider(crypto_provider())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(root_store)
+            .with_no_client_auth();
+        let connector = HttpsConnectorBuilder::new()
+            .with_tls_config(tls)
+            .https_or_http()
+            .enable_http1()
+            .build();
+
+        Ok(Self {
+            base_url: base_url.into().trim_end_matches('/').into(),
+            client: Client::builder(TokioExecutor::new()).build(connector),
+            cookies: Arc::default(),
+        })
+    }
+
+    /// A client for the same server which starts without cookies, e.g. to act as a
+    /// second user.
+    #[must_use]
+    pub fn fresh(&self) -> Self {
+        Self {
+            cookies: Arc::default(),
+            ..self.clone()
+        }
+    }
+
+    /// The absolute URL of `path` on the server.
+    #[must_use]
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path.trim_start_matches('/'))
+    }
+
+    pub fn request(&self, method: Method, path: &str) -> TestRequest {
+        TestRequest {
+            client: self.clone(),
+            method,
+            url: self.url(path),
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+        }
+    }
+
+    pub fn get(&self, path: &str) -> TestRequest {
+        self.request(Method::GET, path)
+    }
+
+    pub fn post(&self, path: &str) -> TestRequest {
+        self.request(Method::POST, path)
+    }
+
+    pub fn put(&self, path: &str) -> TestRequest {
+        self.request(Method::PUT, path)
+    }
+
+    pub fn patch(&self, path: &str) -> TestRequest {
+        self.request(Method::PATCH, path)
+    }
+
+    pub fn delete(&self, path: &str) -> TestRequest {
+        self.request(Method::DELETE, path)
+    }
+
+    /// The value of the cookie `name`, if the server has set it.
+    #[must_use]
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.jar().get(name).cloned()
+    }
+
+    /// Send the cookie `name` with every following request.
+    pub fn set_cookie(&self, name: impl Into<String>, value: impl Into<String>) {
+        self.jar().insert(name.into(), value.into());
+    }
+
+    pub fn clear_cookies(&self) {
+        self.jar().clear();
+    }
+
+    fn jar(&self) -> MutexGuard<'_, BTreeMap<String, String>> {
+        self.cookies.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn cookie_header(&self) -> Option<HeaderValue> {
+        let cookies = self
+            .jar()
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("; ");
+        if cookies.is_empty() {
+            return None;
+        }
+        HeaderValue::from_str(&cookies).ok()
+    }
+
+    /// Store the cookies set by a response, forgetting those it expires.
+    fn store_cookies(&self, headers: &HeaderMap) {
+        let mut jar = self.jar();
+        for (name, value, expired) in headers
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(parse_set_cookie)
+        {
+            if expired {
+                jar.remove(&name);
+            } else {
+                jar.insert(name, value);
+            }
+        }
+    }
+}
+
+/// The name and value of a `Set-Cookie` header, and whether it expires the cookie.
+fn parse_set_cookie(header: &HeaderValue) -> Option<(String, String, bool)> {
+    let header = header.to_str().ok()?;
+    let mut parts = header.split(';').map(str::trim);
+    let (name, value) = parts.next()?.split_once('=')?;
+    let expired = parts.any(|attribute| {
+        attribute.split_once('=').is_some_and(|(key, value)| {
+            key.eq_ignore_ascii_case("max-age")
+                && value.trim().parse::<i64>().is_ok_and(|age| age <= 0)
+        })
+    });
+    Some((name.trim().to_owned(), value.trim().to_owned(), expired))
+}
+
+/// A request being built by a [`TestClient`].  Await it, or call
+/// [`send`](TestRequest::send), to get the response.
+#[must_use = "requests are only sent when awaited"]
+#[derive(Debug)]
+pub struct TestRequest {
+    client: TestClient,
+    method: Method,
+    url: String,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl TestRequest {
+    /// # Panics
+    ///
+    /// Panics if `name` or `value` is not a valid header.
+    pub fn header<K, V>(mut self, name: K, value: V) -> Self
+    where
+        K: TryInto<HeaderName>,
+        K::Error: Debug,
+        V: TryInto<HeaderValue>,
+        V::Error: Debug,
+    {
+        self.headers.append(
+            name.try_into().expect("a valid header name"),
+            value.try_into().expect("a valid header value"),
+        );
+        self
+    }
+
+    /// Send `body` serialized as JSON.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `body` cannot be serialized.
+    pub fn json<T: Serialize + ?Sized>(mut self, body: &T) -> Self {
+        self.body = serde_json::to_vec(body)
+            .expect("a JSON serializable body")
+            .into();
+        self.headers
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        self
+    }
+
+    /// Send `body` as plain text.
+    pub fn text(mut self, body: impl Into<String>) -> Self {
+        self.body = Bytes::from(body.into());
+        self.headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; charset=utf-8"),
+        );
+        self
+    }
+
+    pub fn bytes(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Send the request and read the whole response, storing any cookies it sets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the request fails, so that tests fail with the cause.
+    pub async fn send(self) -> TestResponse {
+        let Self {
+            client,
+            method,
+            url,
+            mut headers,
+            body,
+        } = self;
+
+        if !headers.contains_key(COOKIE) {
+            if let Some(cookies) = client.cookie_header() {
+                headers.insert(COOKIE, cookies);
+            }
+        }
+
+        let mut request = Request::builder()
+            .method(method.clone())
+            .uri(&url)
+            .body(Full::new(body))
+            .unwrap_or_else(|e| panic!("invalid request {method} {url}: {e}"));
+        *request.headers_mut() = headers;
+
+        let response = client
+            .client
+            .request(request)
+            .await
+            .unwrap_or_else(|e| panic!("{method} {url} failed: {e:?}"));
+        let (parts, body) = response.into_parts();
+        let body = body
+            .collect()
+            .await
+            .unwrap_or_else(|e| panic!("reading the response to {method} {url} failed: {e}"))
+            .to_bytes();
+
+        client.store_cookies(&parts.headers);
+        TestResponse {
+            method,
+            url,
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        }
+    }
+}
+
+impl IntoFuture for TestRequest {
+    type Output = TestResponse;
+    type IntoFuture = Pin<Box<dyn Future<Output = TestResponse> + Send>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.send())
+    }
+}
+
+/// A complete response, with assertions which panic with the request and body when they
+/// fail.  The assertions return the response so that they can be chained.
+#[derive(Debug, Clone)]
+pub struct TestResponse {
+    method: Method,
+    url: String,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl TestResponse {
+    #[must_use]
+    pub const fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    #[must_use]
+    pub const fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The first value of the header `name`, if it is present and valid text.
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)?.to_str().ok()
+    }
+
+    /// The value of a cookie set by this response.
+    #[must_use]
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.headers
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(parse_set_cookie)
+            .find(|(cookie, _, _)| cookie == name)
+            .map(|(_, value, _)| value)
+    }
+
+    #[must_use]
+    pub const fn bytes(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// The body as text, replacing invalid UTF-8.
+    #[must_use]
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// The body deserialized from JSON.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the body is not valid JSON for `T`.
+    #[must_use]
+    pub fn json<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(&self.body).unwrap_or_else(|e| {
+            panic!(
+                "{}: invalid JSON body: {e}\n{}",
+                self.request(),
+                self.text()
+            )
+        })
+    }
+
+    fn request(&self) -> String {
+        format!("{} {}", self.method, self.url)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the status is not `status`.
+    #[track_caller]
+    pub fn assert_status(&self, status: StatusCode) -> &Self {
+        assert_eq!(
+            self.status,
+            status,
+            "{} responded with {}:\n{}",
+            self.request(),
+            self.status,
+            self.text()
+        );
+        self
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the status is not `200 OK`.
+    #[track_caller]
+    pub fn assert_ok(&self) -> &Self {
+        self.assert_status(StatusCode::OK)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the header `name` is missing or not `value`.
+    #[track_caller]
+    pub fn assert_header(&self, name: &str, value: &str) -> &Self {
+        assert_eq!(
+            self.header(name),
+            Some(value),
+            "header {name} of the response to {}",
+            self.request()
+        );
+        self
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the body is not `expected`.
+    #[track_caller]
+    pub fn assert_text(&self, expected: &str) -> &Self {
+        assert_eq!(
+            self.text(),
+            expected,
+            "body of the response to {}",
+            self.request()
+        );
+        self
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the body does not contain `expected`.
+    #[track_caller]
+    pub fn assert_text_contains(&self, expected: &str) -> &Self {
+        let text = self.text();
+        assert!(
+            text.contains(expected),
+            "body of the response to {} does not contain {expected:?}:\n{text}",
+            self.request()
+        );
+        self
+    }
+
+    /// Compare the body with `expected` as JSON values, ignoring formatting and the order
+    /// of object keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the body is not JSON equal to `expected`.
+    #[track_caller]
+    pub fn assert_json<T: Serialize + ?Sized>(&self, expected: &T) -> &Self {
+        let expected = serde_json::to_value(expected).expect("a JSON serializable value");
+        assert_eq!(
+            self.json::<serde_json::Value>(),
+            expected,
+            "JSON body of the response to {}",
+            self.request()
+        );
+        self
+    }
+}
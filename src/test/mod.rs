@@ -0,0 +1,260 @@
+//! In-process end-to-end testing for applications built on grafton-server.
+//!
+//! [`TestServer`] starts a real server on an ephemeral loopback port and sends requests
+//! to it with a [`TestClient`], which keeps cookies between requests and has helpers for
+//! JSON bodies and assertions on responses.
+//!
+//! ```no_run
+//! use grafton_server::{axum::routing::get, test::TestServer, GraftonRouter};
+//!
+//! # async fn example() {
+//! let server =
+//!     TestServer::new(|_| GraftonRouter::new().route("/", get(|| async { "ok" }))).unwrap();
+//!
+//! server.get("/").await.assert_ok().assert_text("ok");
+//! # }
+//! ```
+//!
+//! Available with the `test-util` feature.
+
+mod client;
+
+pub use client::{TestClient, TestRequest, TestResponse};
+
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    model::Context, util::tls::load_certs, Builder, Config, Error, GraftonRouter, HttpMode,
+    ServerConfigProvider, ServerHandle,
+};
+
+/// Prefix of the temporary directories holding certificates generated by [`tls_config`],
+/// which a [`TestServer`] removes when dropped.
+const CERT_DIR_PREFIX: &str = "grafton-test-";
+
+/// A config which binds a plain HTTP listener to an ephemeral loopback port.
+#[must_use]
+pub fn config() -> Config {
+    let mut config = Config::default();
+    let website = &mut config.website;
+    website.bind_address = [127, 0, 0, 1].into();
+    website.bind_ports.http = 0;
+    website.bind_ports.https = 0;
+    website.public_ports.http = 0;
+    website.public_ports.https = 0;
+    website.shutdown_grace_period_secs = 1;
+    config
+}
+
+/// A config which binds an HTTPS listener to an ephemeral loopback port, serving a
+/// self-signed certificate generated in a new temporary directory.
+#[must_use]
+pub fn tls_config() -> Config {
+    static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+    let dir = std::env::temp_dir().join(format!(
+        "{CERT_DIR_PREFIX}{}-{}",
+        std::process::id(),
+        NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let mut config = config();
+    let website = &mut config.website;
+    website.http_mode = HttpMode::Disabled;
+    website.public_ssl_enabled = true;
+    let ssl = &mut website.bind_ssl_config;
+    ssl.enabled = true;
+    ssl.generate_self_signed = true;
+    ssl.cert_path = dir.join("cert.pem").to_string_lossy().into_owned();
+    ssl.key_path = dir.join("key.pem").to_string_lossy().into_owned();
+    config
+}
+
+/// A running server with a client for sending it requests.
+///
+/// Dropping the server shuts it down without waiting for connections to drain; call
+/// [`TestServer::shutdown`] to wait.
+#[derive(Debug)]
+pub struct TestServer {
+    handle: Option<ServerHandle>,
+    addr: SocketAddr,
+    client: TestClient,
+    cert_dir: Option<PathBuf>,
+}
+
+impl TestServer {
+    /// Serve the router built by `router` over plain HTTP, with the [`config`] test config.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server cannot start.
+    pub fn new<F>(router: F) -> Result<Self, Error>
+    where
+        F: FnOnce(&Arc<Context<Config>>) -> GraftonRouter<Config> + Send + 'static,
+    {
+        Self::start(Builder::new(config()).with_router(router))
+    }
+
+    /// Serve the router built by `router` over HTTPS, with the [`tls_config`] test config.
+    /// The client trusts the generated certificate.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the certificate cannot be generated or the server cannot start.
+    pub fn new_tls<F>(router: F) -> Result<Self, Error>
+    where
+        F: FnOnce(&Arc<Context<Config>>) -> GraftonRouter<Config> + Send + 'static,
+    {
+        Self::start(Builder::new(tls_config()).with_router(router))
+    }
+
+    /// Build and start the server from `builder`, whose config would normally start from
+    /// [`config`] or [`tls_config`].  The client talks to the first TLS listener, or to the
+    /// first TCP listener when none uses TLS, trusting the server's certificate.  Must be
+    /// called from within a tokio runtime.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server cannot be built or started, has no TCP listener, or
+    /// its certificate cannot be read.
+    pub fn start<C>(builder: Builder<C>) -> Result<Self, Error>
+    where
+        C: ServerConfigProvider,
+    {
+        let server = builder.build()?;
+        let ssl_config = server
+            .config
+            .get_server_config()
+            .website
+            .bind_ssl_config
+            .clone();
+        let cert_dir = Path::new(&ssl_config.cert_path)
+            .parent()
+            .filter(|dir| {
+                dir.file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with(CERT_DIR_PREFIX))
+            })
+            .map(Path::to_path_buf);
+
+        let handle = server.start()?;
+        match connect(&handle, &ssl_config.cert_path) {
+            Ok((addr, client)) => Ok(Self {
+                handle: Some(handle),
+                addr,
+                client,
+                cert_dir,
+            }),
+            Err(e) => {
+                handle.shutdown();
+                Err(e)
+            }
+        }
+    }
+
+    /// The address the client sends requests to.
+    #[must_use]
+    pub const fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The absolute URL of `path` on this server.
+    #[must_use]
+    pub fn url(&self, path: &str) -> String {
+        self.client.url(path)
+    }
+
+    /// The client used by the request helpers, which shares their cookies.  Clone it to
+    /// send requests from another task, or create a [`TestClient::fresh`] one for a second
+    /// user.
+    #[must_use]
+    pub const fn client(&self) -> &TestClient {
+        &self.client
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the server has been shut down.
+    #[must_use]
+    pub const fn handle(&self) -> &ServerHandle {
+        self.handle.as_ref().expect("the test server is running")
+    }
+
+    pub fn get(&self, path: &str) -> TestRequest {
+        self.client.get(path)
+    }
+
+    pub fn post(&self, path: &str) -> TestRequest {
+        self.client.post(path)
+    }
+
+    pub fn put(&self, path: &str) -> TestRequest {
+        self.client.put(path)
+    }
+
+    pub fn patch(&self, path: &str) -> TestRequest {
+        self.client.patch(path)
+    }
+
+    pub fn delete(&self, path: &str) -> TestRequest {
+        self.client.delete(path)
+    }
+
+    /// Shut the server down and wait for its connections to drain.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error reported by a listener.
+    pub async fn shutdown(mut self) -> Result<(), Error> {
+        match self.handle.take() {
+            Some(handle) => {
+                handle.shutdown();
+                handle.wait().await
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(handle) = &self.handle {
+            handle.shutdown();
+        }
+        if let Some(dir) = &self.cert_dir {
+            // The generated certificate is only needed while the server runs.
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
+
+/// A client for the first TLS listener of `handle`, or its first TCP listener when none
+/// uses TLS, trusting the certificate at `cert_path`.
+fn connect(handle: &ServerHandle, cert_path: &str) -> Result<(SocketAddr, TestClient), Error> {
+    let listeners = handle.listeners();
+    let (addr, tls) = listeners
+        .iter()
+        .filter(|listener| listener.tls)
+        .chain(listeners)
+        .find_map(|listener| Some((listener.local_addr.socket_addr()?, listener.tls)))
+        .ok_or_else(|| Error::InvalidListenerConfig {
+            listener: "test".into(),
+            cause: "the test server needs a TCP listener".into(),
+        })?;
+
+    if !tls {
+        return Ok((addr, TestClient::new(format!("http://{addr}"), Vec::new())?));
+    }
+
+    let roots = load_certs(Path::new(cert_path)).map_err(|source| Error::TlsFileError {
+        path: cert_path.to_owned(),
+        source,
+    })?;
+    Ok((addr, TestClient::new(format!("https://{addr}"), roots)?))
+}
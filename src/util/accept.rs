@@ -0,0 +1,157 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+/// The first delay after running out of file descriptors or memory while accepting.
+const MIN_BACKOFF: Duration = Duration::from_millis(10);
+
+/// The longest delay between accept attempts while resources stay exhausted.
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+/// How often a listener logs accept errors, summarising the ones it suppressed.
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How the accept loop should react to an accept error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcceptErrorKind {
+    /// A pending connection failed before it was accepted.  Accept the next one at once.
+    Connection,
+    /// The process or system ran out of file descriptors, buffers or memory.  Back off
+    /// until some are released.
+    Exhausted,
+    /// The listening socket itself is unusable.  Stop the listener.
+    Fatal,
+}
+
+impl AcceptErrorKind {
+    #[must_use]
+    pub fn of(error: &io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut => return Self::Connection,
+            io::ErrorKind::InvalidInput => return Self::Fatal,
+            _ => {}
+        }
+
+        #[cfg(unix)]
+        match error.raw_os_error() {
+            // accept(2) reports these pending network errors on Linux, and says to treat
+            // them like EAGAIN.
+            Some(
+                libc::EPROTO
+                | libc::ENETDOWN
+                | libc::ENOPROTOOPT
+                | libc::EHOSTDOWN
+                | libc::EHOSTUNREACH
+                | libc::ENETUNREACH,
+            ) => return Self::Connection,
+            Some(libc::EBADF | libc::ENOTSOCK | libc::EFAULT) => return Self::Fatal,
+            _ => {}
+        }
+
+        // Resource exhaustion (EMFILE, ENFILE, ENOBUFS, ENOMEM) and anything unexpected
+        // back off, so a persistent error cannot spin the loop.
+        Self::Exhausted
+    }
+}
+
+/// Exponential backoff between accept attempts while resources are exhausted.
+#[derive(Debug, Default)]
+pub struct Backoff {
+    next: Option<Duration>,
+}
+
+impl Backoff {
+    /// The delay before the next attempt, doubling each time up to one second.
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next.unwrap_or(MIN_BACKOFF);
+        self.next = Some((delay * 2).min(MAX_BACKOFF));
+        delay
+    }
+
+    pub const fn reset(&mut self) {
+        self.next = None;
+    }
+}
+
+/// Limits accept error logging to one message per interval, so a listener which keeps
+/// failing does not flood the log.
+#[derive(Debug, Default)]
+pub struct ErrorLogLimiter {
+    last_logged: Option<Instant>,
+    suppressed: u64,
+}
+
+impl ErrorLogLimiter {
+    /// Whether an error seen `now` should be logged, and if so how many were suppressed
+    /// since the last one which was.
+    pub fn check(&mut self, now: Instant) -> Option<u64> {
+        if self
+            .last_logged
+            .is_some_and(|last| now.duration_since(last) < LOG_INTERVAL)
+        {
+            self.suppressed += 1;
+            return None;
+        }
+
+        self.last_logged = Some(now);
+        Some(std::mem::take(&mut self.suppressed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_accept_errors() {
+        let kind = |kind| AcceptErrorKind::of(&io::Error::from(kind));
+        assert_eq!(
+            kind(io::ErrorKind::ConnectionAborted),
+            AcceptErrorKind::Connection
+        );
+        assert_eq!(kind(io::ErrorKind::InvalidInput), AcceptErrorKind::Fatal);
+        assert_eq!(kind(io::ErrorKind::OutOfMemory), AcceptErrorKind::Exhausted);
+
+        #[cfg(unix)]
+        {
+            let os = |code| AcceptErrorKind::of(&io::Error::from_raw_os_error(code));
+            assert_eq!(os(libc::EMFILE), AcceptErrorKind::Exhausted);
+            assert_eq!(os(libc::ENFILE), AcceptErrorKind::Exhausted);
+            assert_eq!(os(libc::ENOBUFS), AcceptErrorKind::Exhausted);
+            assert_eq!(os(libc::EPROTO), AcceptErrorKind::Connection);
+            assert_eq!(os(libc::EBADF), AcceptErrorKind::Fatal);
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_limit_and_resets() {
+        let mut backoff = Backoff::default();
+        assert_eq!(backoff.next_delay(), Duration::from_millis(10));
+        assert_eq!(backoff.next_delay(), Duration::from_millis(20));
+        for _ in 0..10 {
+            backoff.next_delay();
+        }
+        assert_eq!(backoff.next_delay(), MAX_BACKOFF);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), MIN_BACKOFF);
+    }
+
+    #[test]
+    fn log_limiter_reports_suppressed_errors() {
+        let mut limiter = ErrorLogLimiter::default();
+        let start = Instant::now();
+
+        assert_eq!(limiter.check(start), Some(0));
+        assert_eq!(limiter.check(start + Duration::from_secs(1)), None);
+        assert_eq!(limiter.check(start + Duration::from_secs(2)), None);
+        assert_eq!(limiter.check(start + LOG_INTERVAL), Some(2));
+        assert_eq!(limiter.check(start + LOG_INTERVAL), None);
+    }
+}
@@ -0,0 +1,611 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use {
+    bytes::Bytes,
+    http_body_util::Full,
+    hyper_rustls::HttpsConnectorBuilder,
+    hyper_util::{client::legacy::Client as HyperClient, rt::TokioExecutor},
+    instant_acme::{
+        Account, AccountCredentials, AuthorizationStatus, ChallengeType, HttpClient, Identifier,
+        NewAccount, NewOrder, Order, OrderStatus,
+    },
+    rcgen::{CertificateParams, CustomExtension, DistinguishedName, KeyPair},
+    rustls_pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer},
+    tokio_rustls::rustls::{ClientConfig, RootCertStore},
+    tokio_util::sync::CancellationToken,
+};
+
+use crate::{
+    axum::{extract::Path as UrlPath, http::StatusCode, routing::get, Router},
+    tracing::{debug, error, info, warn},
+    util::{
+        client_cert::sha256_hex,
+        config::{AcmeChallenge, AcmeConfig},
+        tls::{crypto_provider, load_certified_key, load_certs, CertResolver},
+    },
+    Error,
+};
+
+/// Path prefix under which HTTP-01 challenges are requested.
+pub const HTTP01_PATH: &str = "/.well-known/acme-challenge";
+
+const POLL_ATTEMPTS: u32 = 30;
+const POLL_DELAY_MAX: Duration = Duration::from_secs(10);
+const RETRY_DELAY_MIN: Duration = Duration::from_mins(1);
+const RETRY_DELAY_MAX: Duration = Duration::from_hours(6);
+
+/// Key authorizations for pending HTTP-01 challenges, keyed by token.
+#[derive(Debug, Clone, Default)]
+pub struct Http01Tokens(Arc<RwLock<HashMap<String, String>>>);
+
+impl Http01Tokens {
+    fn insert(&self, token: String, key_authorization: String) {
+        self.0
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .insert(token, key_authorization);
+    }
+
+    fn remove(&self, token: &str) {
+        self.0
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .remove(token);
+    }
+
+    fn get(&self, token: &str) -> Option<String> {
+        self.0
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .get(token)
+            .cloned()
+    }
+}
+
+/// A router which answers HTTP-01 challenges for `tokens`, to be merged into the plain
+/// HTTP listener's router.
+pub fn http01_router(tokens: Http01Tokens) -> Router {
+    Router::new().route(
+        &format!("{HTTP01_PATH}/:token"),
+        get(move |UrlPath(token): UrlPath<String>| {
+            let key_authorization = tokens.get(&token);
+            async move {
+                key_authorization.map_or_else(
+                    || {
+                        debug!("Unknown ACME challenge token {}", token);
+                        Err(StatusCode::NOT_FOUND)
+                    },
+                    Ok,
+                )
+            }
+        }),
+    )
+}
+
+/// Obtains the certificate for the configured domains from the ACME directory, writes it
+/// to the cache directory and loads it into the [`CertResolver`], renewing it before it
+/// expires.
+#[derive(Debug)]
+pub struct AcmeManager {
+    config: AcmeConfig,
+    domains: Vec<String>,
+    resolver: Arc<CertResolver>,
+    tokens: Http01Tokens,
+}
+
+impl AcmeManager {
+    pub const fn new(
+        config: AcmeConfig,
+        domains: Vec<String>,
+        resolver: Arc<CertResolver>,
+        tokens: Http01Tokens,
+    ) -> Self {
+        Self {
+            config,
+            domains,
+            resolver,
+            tokens,
+        }
+    }
+
+    /// The HTTP-01 responses this manager publishes, for [`http01_router`].
+    #[must_use]
+    pub fn http01_tokens(&self) -> Http01Tokens {
+        self.tokens.clone()
+    }
+
+    /// Check the cached certificate now and then every `check_interval_secs`, requesting
+    /// a new one when it is missing or due for renewal, until `shutdown` is cancelled.
+    pub fn spawn(self, shutdown: CancellationToken) {
+        tokio::spawn(async move {
+            let check_interval = Duration::from_secs(self.config.check_interval_secs.max(1));
+            let mut retry_delay = RETRY_DELAY_MIN;
+
+            loop {
+                let delay = if self.renewal_due() {
+                    let result = tokio::select! {
+                        result = self.issue() => result,
+                        () = shutdown.cancelled() => break,
+                    };
+
+                    match result {
+                        Ok(()) => {
+                            retry_delay = RETRY_DELAY_MIN;
+                            check_interval
+                        }
+                        Err(e) => {
+                            error!(
+                                "ACME certificate request failed, retrying in {:?}: {}",
+                                retry_delay, e
+                            );
+                            let delay = retry_delay;
+                            retry_delay = (retry_delay * 2).min(RETRY_DELAY_MAX);
+                            delay
+                        }
+                    }
+                } else {
+                    check_interval
+                };
+
+                tokio::select! {
+                    () = tokio::time::sleep(delay) => {}
+                    () = shutdown.cancelled() => break,
+                }
+            }
+        });
+    }
+
+    /// Whether the cached certificate is missing or unusable, does not cover every domain,
+    /// or expires within `renew_before_days`.
+    fn renewal_due(&self) -> bool {
+        let renew_before = Duration::from_secs(self.config.renew_before_days * 24 * 60 * 60);
+        renewal_due(
+            &self.config.cert_path(),
+            &self.config.key_path(),
+            &self.domains,
+            renew_before,
+        )
+    }
+
+    async fn issue(&self) -> Result<(), Error> {
+        info!(
+            "Requesting a certificate for {:?} from {}",
+            self.domains, self.config.directory_url
+        );
+
+        let account = self.account().await?;
+        let identifiers: Vec<Identifier> = self
+            .domains
+            .iter()
+            .map(|domain| Identifier::Dns(domain.clone()))
+            .collect();
+        let mut order = account
+            .new_order(&NewOrder {
+                identifiers: &identifiers,
+            })
+            .await?;
+
+        let mut pending = Vec::new();
+        let result = match self.prepare_challenges(&mut order, &mut pending).await {
+            Ok(()) => self.complete_order(&mut order).await,
+            Err(e) => Err(e),
+        };
+
+        for (domain, token) in pending {
+            self.tokens.remove(&token);
+            self.resolver.remove_challenge(&domain);
+        }
+
+        let (chain, key_pem) = result?;
+        let key_path = self.config.key_path();
+        let cert_path = self.config.cert_path();
+        // Write both files before replacing either, so that a failed write keeps the
+        // previous pair.
+        let key_temp = write_private_temp(&key_path, key_pem.as_bytes())?;
+        let cert_temp = write_private_temp(&cert_path, chain.as_bytes())?;
+        fs::rename(key_temp, key_path)?;
+        fs::rename(cert_temp, cert_path)?;
+        self.resolver.reload()?;
+
+        info!("Installed ACME certificate for {:?}", self.domains);
+        Ok(())
+    }
+
+    /// Publish a response for every pending authorization and tell the server it can
+    /// validate them, recording the published challenges in `pending`.
+    async fn prepare_challenges(
+        &self,
+        order: &mut Order,
+        pending: &mut Vec<(String, String)>,
+    ) -> Result<(), Error> {
+        let challenge_type = match self.config.challenge {
+            AcmeChallenge::Http01 => ChallengeType::Http01,
+            AcmeChallenge::TlsAlpn01 => ChallengeType::TlsAlpn01,
+        };
+
+        let mut ready = Vec::new();
+        for authorization in order.authorizations().await? {
+            let Identifier::Dns(domain) = &authorization.identifier;
+
+            match authorization.status {
+                AuthorizationStatus::Pending => {}
+                AuthorizationStatus::Valid => continue,
+                status => {
+                    return Err(Error::AcmeOrderFailed(format!(
+                        "authorization for {domain} is {status:?}"
+                    )))
+                }
+            }
+
+            let challenge = authorization
+                .challenges
+                .iter()
+                .find(|challenge| challenge.r#type == challenge_type)
+                .ok_or_else(|| {
+                    Error::AcmeOrderFailed(format!(
+                        "no {} challenge offered for {domain}",
+                        self.config.challenge
+                    ))
+                })?;
+
+            let key_authorization = order.key_authorization(challenge);
+            match self.config.challenge {
+                AcmeChallenge::Http01 => self
+                    .tokens
+                    .insert(challenge.token.clone(), key_authorization.as_str().into()),
+                AcmeChallenge::TlsAlpn01 => {
+                    let (cert, key) =
+                        tls_alpn_certificate(domain, key_authorization.digest().as_ref())?;
+                    self.resolver.add_challenge(domain, cert, key)?;
+                }
+            }
+
+            debug!(
+                "Published {} challenge for {}",
+                self.config.challenge, domain
+            );
+            pending.push((domain.clone(), challenge.token.clone()));
+            ready.push(challenge.url.clone());
+        }
+
+        for url in ready {
+            order.set_challenge_ready(&url).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Wait for the order to become ready, then finalize it and download the chain,
+    /// returning the chain and the new private key as PEM.
+    async fn complete_order(&self, order: &mut Order) -> Result<(String, String), Error> {
+        let mut delay = Duration::from_millis(250);
+        let mut attempts = 0;
+
+        loop {
+            let state = order.refresh().await?;
+            match state.status {
+                OrderStatus::Ready => break,
+                OrderStatus::Invalid | OrderStatus::Valid => {
+                    return Err(Error::AcmeOrderFailed(format!(
+                        "order is {:?}: {:?}",
+                        state.status, state.error
+                    )))
+                }
+                OrderStatus::Pending | OrderStatus::Processing => {}
+            }
+
+            attempts += 1;
+            if attempts >= POLL_ATTEMPTS {
+                return Err(Error::AcmeOrderFailed(
+                    "timed out waiting for the challenges to be validated".into(),
+                ));
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(POLL_DELAY_MAX);
+        }
+
+        let key_pair = KeyPair::generate()?;
+        let mut params = CertificateParams::new(self.domains.clone())?;
+        params.distinguished_name = DistinguishedName::new();
+        let csr = params.serialize_request(&key_pair)?;
+        order.finalize(csr.der()).await?;
+
+        let mut delay = Duration::from_millis(250);
+        for _ in 0..POLL_ATTEMPTS {
+            if let Some(chain) = order.certificate().await? {
+                return Ok((chain, key_pair.serialize_pem()));
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(POLL_DELAY_MAX);
+        }
+
+        Err(Error::AcmeOrderFailed(
+            "timed out waiting for the certificate to be issued".into(),
+        ))
+    }
+
+    /// Restore the cached account for the directory, or register a new one.
+    async fn account(&self) -> Result<Account, Error> {
+        let path = self.account_path();
+        let http = self.http_client()?;
+
+        if let Ok(contents) = fs::read(&path) {
+            let credentials: AccountCredentials = serde_json::from_slice(&contents)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            debug!("Using cached ACME account from {:?}", path);
+
+            return Ok(match http {
+                Some(http) => Account::from_credentials_and_http(credentials, http).await?,
+                None => Account::from_credentials(credentials).await?,
+            });
+        }
+
+        if !self.config.terms_of_service_agreed {
+            return Err(Error::AcmeOrderFailed(format!(
+                "set acme.terms_of_service_agreed to register an account with {}",
+                self.config.directory_url
+            )));
+        }
+
+        let contact: Vec<&str> = self.config.contact.iter().map(String::as_str).collect();
+        let new_account = NewAccount {
+            contact: &contact,
+            terms_of_service_agreed: true,
+            only_return_existing: false,
+        };
+
+        let (account, credentials) = match http {
+            Some(http) => {
+                Account::create_with_http(&new_account, &self.config.directory_url, None, http)
+                    .await?
+            }
+            None => Account::create(&new_account, &self.config.directory_url, None).await?,
+        };
+
+        let credentials = serde_json::to_vec(&credentials)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        write_private(&path, &credentials)?;
+        info!(
+            "Registered ACME account {} with {}",
+            account.id(),
+            self.config.directory_url
+        );
+
+        Ok(account)
+    }
+
+    /// Accounts are cached per directory, so switching between staging and production
+    /// does not reuse credentials the other directory does not know.
+    fn account_path(&self) -> PathBuf {
+        let directory = sha256_hex(self.config.directory_url.as_bytes());
+        self.config
+            .cache_dir
+            .join(format!("account-{}.json", &directory[..16]))
+    }
+
+    /// An HTTP client trusting `ca_roots_path`, or `None` to use the platform's roots.
+    fn http_client(&self) -> Result<Option<Box<dyn HttpClient>>, Error> {
+        let Some(ca_roots_path) = &self.config.ca_roots_path else {
+            return Ok(None);
+        };
+
+        let mut roots = RootCertStore::empty();
+        let certs = load_certs(Path::new(ca_roots_path)).map_err(|source| Error::TlsFileError {
+            path: ca_roots_path.clone(),
+            source,
+        })?;
+        roots.add_parsable_certificates(certs);
+
+        let tls = ClientConfig::builder_with_provider(crypto_provider())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = HttpsConnectorBuilder::new()
+            .with_tls_config(tls)
+            .https_only()
+            .enable_http1()
+            .build();
+
+        Ok(Some(Box::new(
+            HyperClient::builder(TokioExecutor::new()).build::<_, Full<Bytes>>(connector),
+        )))
+    }
+}
+
+/// A self-signed certificate carrying the `acmeIdentifier` extension for TLS-ALPN-01.
+fn tls_alpn_certificate(
+    domain: &str,
+    key_authorization_digest: &[u8],
+) -> Result<
+    (
+        rustls_pki_types::CertificateDer<'static>,
+        PrivateKeyDer<'static>,
+    ),
+    Error,
+> {
+    let key_pair = KeyPair::generate()?;
+    let mut params = CertificateParams::new(vec![domain.to_string()])?;
+    params.custom_extensions = vec![CustomExtension::new_acme_identifier(
+        key_authorization_digest,
+    )];
+    let cert = params.self_signed(&key_pair)?;
+
+    Ok((
+        cert.der().clone(),
+        PrivatePkcs8KeyDer::from(key_pair.serialize_der()).into(),
+    ))
+}
+
+fn renewal_due(
+    cert_path: &Path,
+    key_path: &Path,
+    domains: &[String],
+    renew_before: Duration,
+) -> bool {
+    if !cert_path.exists() {
+        return true;
+    }
+    let loaded = load_certified_key(
+        &cert_path.to_string_lossy(),
+        &key_path.to_string_lossy(),
+        &crypto_provider(),
+    );
+    let info = match loaded {
+        Ok((_, info)) => info,
+        Err(e) => {
+            warn!("Cached ACME certificate cannot be used: {}", e);
+            return true;
+        }
+    };
+
+    if let Some(missing) = domains.iter().find(|domain| !info.covers(domain)) {
+        info!("Cached ACME certificate does not cover {}", missing);
+        return true;
+    }
+
+    let remaining = info
+        .not_after
+        .duration_since(SystemTime::now())
+        .unwrap_or_default();
+
+    debug!(
+        "Cached ACME certificate expires in {} days",
+        info.days_until_expiry()
+    );
+    remaining <= renew_before
+}
+
+/// Write a file only the current user can read, replacing any previous version in one
+/// step.
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp_path = write_private_temp(path, contents)?;
+    fs::rename(temp_path, path)
+}
+
+/// Write a file only the current user can read next to `path`, returning its path so that
+/// it can be renamed over `path`.
+fn write_private_temp(path: &Path, contents: &[u8]) -> io::Result<PathBuf> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let temp_path = path.with_extension("tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    io::Write::write_all(&mut options.open(&temp_path)?, contents)?;
+    Ok(temp_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tower::ServiceExt;
+    use x509_parser::{certificate::X509Certificate, oid_registry::Oid, prelude::FromDer};
+
+    use super::*;
+    use crate::axum::{body::Body, http::Request};
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("grafton-acme-{}-{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Write a certificate for `names` to `dir`, with its key unless `mismatched_key`.
+    fn write_cert(dir: &Path, names: &[&str], valid_for: time::Duration, mismatched_key: bool) {
+        let mut params =
+            CertificateParams::new(names.iter().map(ToString::to_string).collect::<Vec<_>>())
+                .unwrap();
+        params.not_after = time::OffsetDateTime::now_utc() + valid_for;
+        let key = KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+        let key = if mismatched_key {
+            KeyPair::generate().unwrap()
+        } else {
+            key
+        };
+        fs::write(dir.join("certificate.pem"), cert.pem()).unwrap();
+        fs::write(dir.join("private_key.pem"), key.serialize_pem()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn http01_router_serves_known_tokens() {
+        let tokens = Http01Tokens::default();
+        tokens.insert("abc".into(), "abc.thumbprint".into());
+        let router = http01_router(tokens);
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::get("/.well-known/acme-challenge/abc")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = router
+            .oneshot(
+                Request::get("/.well-known/acme-challenge/other")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn renewal_is_due_when_missing_mismatched_expiring_or_incomplete() {
+        let dir = test_dir("renewal");
+        let cert_path = dir.join("certificate.pem");
+        let key_path = dir.join("private_key.pem");
+        let domains = vec!["example.com".to_string()];
+        let thirty_days = Duration::from_hours(30 * 24);
+        let due = || renewal_due(&cert_path, &key_path, &domains, thirty_days);
+
+        assert!(due());
+
+        write_cert(&dir, &["example.com"], time::Duration::days(90), false);
+        assert!(!due());
+
+        write_cert(&dir, &["example.com"], time::Duration::days(90), true);
+        assert!(due());
+
+        write_cert(&dir, &["example.com"], time::Duration::days(10), false);
+        assert!(due());
+
+        write_cert(&dir, &["other.example"], time::Duration::days(90), false);
+        assert!(due());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn tls_alpn_certificate_carries_acme_identifier() {
+        let digest = [7u8; 32];
+        let (cert, _) = tls_alpn_certificate("example.com", &digest).unwrap();
+        let (_, parsed) = X509Certificate::from_der(&cert).unwrap();
+
+        let acme_identifier = Oid::from(&[1, 3, 6, 1, 5, 5, 7, 1, 31]).unwrap();
+        let extension = parsed
+            .extensions()
+            .iter()
+            .find(|ext| ext.oid == acme_identifier)
+            .unwrap();
+        assert!(extension.critical);
+        assert!(extension.value.ends_with(&digest));
+    }
+}
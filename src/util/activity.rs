@@ -0,0 +1,221 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use {
+    bytes::Bytes,
+    hyper::body::{Body as HttpBody, Frame, SizeHint},
+    tokio::{
+        io::{AsyncRead, AsyncWrite, ReadBuf},
+        time::Instant,
+    },
+};
+
+use crate::axum::body::Body;
+
+/// Tracks when a connection last moved data and how many of its requests are in
+/// progress, to find connections which have gone idle.
+#[derive(Debug)]
+pub struct Activity {
+    started: Instant,
+    last_active_millis: AtomicU64,
+    in_flight: AtomicUsize,
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            last_active_millis: AtomicU64::new(0),
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Activity {
+    pub fn touch(&self) {
+        let elapsed = u64::try_from(self.started.elapsed().as_millis()).unwrap_or(u64::MAX);
+        self.last_active_millis
+            .fetch_max(elapsed, Ordering::Relaxed);
+    }
+
+    /// Mark a request as in progress until the returned guard is dropped.
+    pub fn start_request(self: &Arc<Self>) -> RequestGuard {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        RequestGuard(Arc::clone(self))
+    }
+
+    fn last_active(&self) -> Instant {
+        self.started + Duration::from_millis(self.last_active_millis.load(Ordering::Relaxed))
+    }
+
+    /// Wait until no request has been in progress and no data has moved for `timeout`.
+    pub async fn idle(&self, timeout: Duration) {
+        loop {
+            let deadline = self.last_active() + timeout;
+            if self.in_flight.load(Ordering::Relaxed) > 0 {
+                tokio::time::sleep(timeout).await;
+            } else if deadline <= Instant::now() {
+                return;
+            } else {
+                tokio::time::sleep_until(deadline).await;
+            }
+        }
+    }
+}
+
+/// Keeps a request counted as in progress by [`Activity::start_request`].
+pub struct RequestGuard(Arc<Activity>);
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+        self.0.touch();
+    }
+}
+
+/// A response body which keeps its request counted as in progress until the body has
+/// been sent or dropped, so that streaming responses are not cut off as idle.
+pub struct TrackedBody {
+    body: Body,
+    _request: RequestGuard,
+}
+
+impl TrackedBody {
+    pub const fn new(body: Body, request: RequestGuard) -> Self {
+        Self {
+            body,
+            _request: request,
+        }
+    }
+}
+
+impl HttpBody for TrackedBody {
+    type Data = Bytes;
+    type Error = crate::axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(&mut self.body).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+/// A connection which records its reads and writes in an [`Activity`].
+pub struct ActivityIo<I> {
+    io: I,
+    activity: Arc<Activity>,
+}
+
+impl<I> ActivityIo<I> {
+    pub const fn new(io: I, activity: Arc<Activity>) -> Self {
+        Self { io, activity }
+    }
+
+    fn record<T>(&self, poll: Poll<io::Result<T>>) -> Poll<io::Result<T>> {
+        if matches!(poll, Poll::Ready(Ok(_))) {
+            self.activity.touch();
+        }
+        poll
+    }
+}
+
+impl<I: AsyncRead + Unpin> AsyncRead for ActivityIo<I> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.io).poll_read(cx, buf);
+        this.record(poll)
+    }
+}
+
+impl<I: AsyncWrite + Unpin> AsyncWrite for ActivityIo<I> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.io).poll_write(cx, buf);
+        this.record(poll)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.io).poll_write_vectored(cx, bufs);
+        this.record(poll)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_waits_for_requests_and_traffic() {
+        let activity = Arc::new(Activity::default());
+        let timeout = Duration::from_mins(1);
+
+        let guard = activity.start_request();
+        let idle = tokio::time::timeout(Duration::from_mins(2), activity.idle(timeout));
+        assert!(idle.await.is_err(), "a request in progress is not idle");
+
+        drop(guard);
+        tokio::time::advance(Duration::from_secs(30)).await;
+        activity.touch();
+
+        let start = Instant::now();
+        activity.idle(timeout).await;
+        assert_eq!(start.elapsed(), timeout);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn response_body_keeps_request_in_progress() {
+        let activity = Arc::new(Activity::default());
+        let timeout = Duration::from_mins(1);
+
+        let body = TrackedBody::new(Body::from("ok"), activity.start_request());
+        let idle = tokio::time::timeout(Duration::from_mins(2), activity.idle(timeout));
+        assert!(idle.await.is_err(), "an unsent response is not idle");
+
+        drop(body);
+        let start = Instant::now();
+        activity.idle(timeout).await;
+        assert_eq!(start.elapsed(), timeout);
+    }
+}
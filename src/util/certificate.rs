@@ -0,0 +1,318 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use {
+    rustls_pki_types::CertificateDer,
+    tokio_util::sync::CancellationToken,
+    x509_parser::{
+        certificate::X509Certificate, extensions::GeneralName, prelude::FromDer, time::ASN1Time,
+    },
+};
+
+use crate::{
+    tracing::{error, warn},
+    util::{
+        client_cert::{format_ip, sha256_hex},
+        tls::CertResolver,
+    },
+};
+
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Details of a loaded server certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    /// DNS names and IP addresses from the subject alternative names.
+    pub server_names: Vec<String>,
+    pub not_before: SystemTime,
+    pub not_after: SystemTime,
+    /// Lowercase hex SHA-256 digest of the certificate.
+    pub fingerprint_sha256: String,
+}
+
+impl CertificateInfo {
+    /// Parse a DER certificate.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the problem if `cert` is not a valid X.509 certificate.
+    pub fn from_der(cert: &CertificateDer) -> Result<Self, String> {
+        let (_, parsed) = X509Certificate::from_der(cert).map_err(|e| e.to_string())?;
+
+        let server_names = subject_alt_names(&parsed)
+            .iter()
+            .filter_map(|name| match name {
+                GeneralName::DNSName(dns) => Some((*dns).to_string()),
+                GeneralName::IPAddress(bytes) => format_ip(bytes).map(|ip| ip.to_string()),
+                _ => None,
+            })
+            .collect();
+
+        Ok(Self {
+            subject: parsed.subject().to_string(),
+            issuer: parsed.issuer().to_string(),
+            server_names,
+            not_before: system_time(parsed.validity().not_before),
+            not_after: system_time(parsed.validity().not_after),
+            fingerprint_sha256: sha256_hex(cert),
+        })
+    }
+
+    /// Whole days until the certificate expires, negative once it has expired.
+    #[must_use]
+    pub fn days_until_expiry(&self) -> i64 {
+        let secs = match self.not_after.duration_since(SystemTime::now()) {
+            Ok(remaining) => i64::try_from(remaining.as_secs()).unwrap_or(i64::MAX),
+            Err(e) => -i64::try_from(e.duration().as_secs()).unwrap_or(i64::MAX),
+        };
+        secs.div_euclid(SECS_PER_DAY)
+    }
+
+    /// Whether the certificate is valid for `hostname`, where a wildcard name matches
+    /// exactly one label.
+    #[must_use]
+    pub fn covers(&self, hostname: &str) -> bool {
+        let hostname = hostname.trim_end_matches('.').to_ascii_lowercase();
+
+        self.server_names.iter().any(|name| {
+            let name = name.to_ascii_lowercase();
+            name == hostname
+                || name.strip_prefix("*.").is_some_and(|parent| {
+                    hostname
+                        .split_once('.')
+                        .is_some_and(|(_, rest)| rest == parent)
+                })
+        })
+    }
+}
+
+/// Check that `chain` is ordered leaf first with each certificate issued by the next,
+/// returning details of the leaf.  Certificates which are expired or not yet valid are
+/// logged but still accepted, so that the server keeps running until they are replaced.
+///
+/// # Errors
+///
+/// Returns a description of the first ordering problem found.
+pub fn inspect_chain(chain: &[CertificateDer]) -> Result<CertificateInfo, String> {
+    let leaf = chain.first().ok_or("the chain is empty")?;
+
+    let parsed = chain
+        .iter()
+        .enumerate()
+        .map(|(i, cert)| {
+            X509Certificate::from_der(cert)
+                .map(|(_, parsed)| parsed)
+                .map_err(|e| format!("certificate {i} cannot be parsed: {e}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for (i, pair) in parsed.windows(2).enumerate() {
+        if pair[0].issuer().as_raw() != pair[1].subject().as_raw() {
+            return Err(format!(
+                "certificate {} ({}) is not the issuer of certificate {i} ({}); the chain must be ordered leaf first",
+                i + 1,
+                pair[1].subject(),
+                pair[0].subject()
+            ));
+        }
+    }
+
+    let now = ASN1Time::now();
+    for (i, cert) in parsed.iter().enumerate() {
+        let validity = cert.validity();
+        if now < validity.not_before {
+            warn!(
+                "Certificate {} ({}) is not valid until {}",
+                i,
+                cert.subject(),
+                validity.not_before
+            );
+        }
+        if now > validity.not_after {
+            warn!(
+                "Certificate {} ({}) expired on {}",
+                i,
+                cert.subject(),
+                validity.not_after
+            );
+        }
+    }
+
+    CertificateInfo::from_der(leaf)
+}
+
+/// The DNS subject alternative names of a certificate.
+pub(super) fn certificate_dns_names(cert: &CertificateDer) -> Vec<String> {
+    let Ok((_, parsed)) = X509Certificate::from_der(cert) else {
+        return Vec::new();
+    };
+
+    subject_alt_names(&parsed)
+        .iter()
+        .filter_map(|name| match name {
+            GeneralName::DNSName(dns) => Some((*dns).to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn subject_alt_names<'a>(cert: &'a X509Certificate) -> Vec<GeneralName<'a>> {
+    cert.subject_alternative_name()
+        .ok()
+        .flatten()
+        .map(|san| san.value.general_names.clone())
+        .unwrap_or_default()
+}
+
+pub(super) fn system_time(time: ASN1Time) -> SystemTime {
+    let secs = time.timestamp();
+    let offset = Duration::from_secs(secs.unsigned_abs());
+    if secs >= 0 {
+        UNIX_EPOCH + offset
+    } else {
+        UNIX_EPOCH - offset
+    }
+}
+
+/// Check the expiry of every loaded certificate every `interval`, logging a warning the
+/// first time a certificate is within each of `warning_days`, and an error once it has
+/// expired, until `shutdown` is cancelled.
+pub fn spawn_expiry_task(
+    resolver: Arc<CertResolver>,
+    interval: Duration,
+    warning_days: Vec<u64>,
+    shutdown: CancellationToken,
+) {
+    if interval.is_zero() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        let mut reported = HashMap::new();
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                () = shutdown.cancelled() => break,
+            }
+
+            for info in resolver.certificates() {
+                let days = info.days_until_expiry();
+                let threshold = expiry_threshold(days, &warning_days);
+
+                if reported.get(&info.fingerprint_sha256) == Some(&threshold) {
+                    continue;
+                }
+                reported.insert(info.fingerprint_sha256.clone(), threshold);
+
+                match threshold {
+                    Some(0) => error!(
+                        "TLS certificate {} for {:?} has expired",
+                        info.subject, info.server_names
+                    ),
+                    Some(_) => warn!(
+                        "TLS certificate {} for {:?} expires in {} days",
+                        info.subject, info.server_names, days
+                    ),
+                    None => {}
+                }
+            }
+        }
+    });
+}
+
+/// The smallest of `warning_days` which `days` is within, or `Some(0)` once expired.
+fn expiry_threshold(days: i64, warning_days: &[u64]) -> Option<u64> {
+    let Ok(days) = u64::try_from(days) else {
+        return Some(0);
+    };
+
+    warning_days
+        .iter()
+        .copied()
+        .filter(|&threshold| threshold > 0 && days < threshold)
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair};
+    use time::OffsetDateTime;
+
+    use super::*;
+
+    fn cert(names: &[&str], days_valid: i64) -> CertificateDer<'static> {
+        let mut params =
+            CertificateParams::new(names.iter().map(ToString::to_string).collect::<Vec<_>>())
+                .unwrap();
+        params.not_after = OffsetDateTime::now_utc() + time::Duration::days(days_valid);
+        params
+            .self_signed(&KeyPair::generate().unwrap())
+            .unwrap()
+            .der()
+            .clone()
+    }
+
+    #[test]
+    fn info_reports_names_and_expiry() {
+        let info = CertificateInfo::from_der(&cert(&["example.com", "127.0.0.1"], 90)).unwrap();
+
+        assert_eq!(info.server_names, ["example.com", "127.0.0.1"]);
+        assert_eq!(info.days_until_expiry(), 89);
+        assert!(info.covers("EXAMPLE.com."));
+        assert!(info.covers("127.0.0.1"));
+        assert!(!info.covers("www.example.com"));
+    }
+
+    #[test]
+    fn wildcards_cover_one_label() {
+        let info = CertificateInfo::from_der(&cert(&["*.example.com"], 90)).unwrap();
+
+        assert!(info.covers("www.example.com"));
+        assert!(!info.covers("example.com"));
+        assert!(!info.covers("a.b.example.com"));
+    }
+
+    #[test]
+    fn chain_must_be_ordered() {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params
+            .distinguished_name
+            .push(DnType::CommonName, "grafton test CA");
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        let leaf = CertificateParams::new(vec!["example.com".to_string()])
+            .unwrap()
+            .signed_by(&KeyPair::generate().unwrap(), &ca, &ca_key)
+            .unwrap();
+
+        let ordered = [leaf.der().clone(), ca.der().clone()];
+        assert!(inspect_chain(&ordered).is_ok());
+
+        let reversed = [ca.der().clone(), leaf.der().clone()];
+        assert!(inspect_chain(&reversed)
+            .unwrap_err()
+            .contains("ordered leaf first"));
+
+        let expired = cert(&["example.com"], -1);
+        assert!(inspect_chain(&[expired]).unwrap().days_until_expiry() < 0);
+    }
+
+    #[test]
+    fn expiry_thresholds() {
+        let warning_days = [30, 7];
+
+        assert_eq!(expiry_threshold(60, &warning_days), None);
+        assert_eq!(expiry_threshold(20, &warning_days), Some(30));
+        assert_eq!(expiry_threshold(3, &warning_days), Some(7));
+        assert_eq!(expiry_threshold(-1, &warning_days), Some(0));
+    }
+}
@@ -0,0 +1,140 @@
+use std::fmt::Write as _;
+
+use {
+    rustls_pki_types::CertificateDer,
+    sha2::{Digest, Sha256},
+    tokio_rustls::rustls::server::ServerConnection,
+    x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer},
+};
+
+use crate::{
+    axum::{
+        async_trait,
+        extract::FromRequestParts,
+        http::{request::Parts, StatusCode},
+    },
+    tracing::warn,
+};
+
+/// The verified certificate chain a client presented during a mutual TLS handshake.
+///
+/// Use it as an extractor to require a client certificate, or as
+/// `Option<ClientCertificate>` when client authentication is optional.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    /// The presented chain, leaf first.
+    pub chain: Vec<CertificateDer<'static>>,
+    /// The leaf certificate's subject, e.g. `CN=service-a, O=Example`.
+    pub subject: String,
+    pub issuer: String,
+    /// Subject alternative names formatted as `DNS:name`, `IP:addr`, `URI:uri` or `email:addr`.
+    pub subject_alt_names: Vec<String>,
+    /// Lowercase hex SHA-256 digest of the leaf certificate.
+    pub fingerprint_sha256: String,
+}
+
+impl ClientCertificate {
+    /// The client certificate of an established connection, if one was presented.
+    pub(crate) fn from_connection(connection: &ServerConnection) -> Option<Self> {
+        Self::from_chain(connection.peer_certificates()?)
+    }
+
+    /// The client certificate for a verified `chain`, if it is not empty.
+    pub(crate) fn from_chain(chain: &[CertificateDer<'static>]) -> Option<Self> {
+        let chain = chain.to_vec();
+        let leaf = chain.first()?;
+
+        let (_, parsed) = X509Certificate::from_der(leaf)
+            .inspect_err(|e| warn!("Failed to parse client certificate: {}", e))
+            .ok()?;
+
+        let subject_alt_names = parsed
+            .subject_alternative_name()
+            .ok()
+            .flatten()
+            .map(|san| {
+                san.value
+                    .general_names
+                    .iter()
+                    .filter_map(format_general_name)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(Self {
+            subject: parsed.subject().to_string(),
+            issuer: parsed.issuer().to_string(),
+            subject_alt_names,
+            fingerprint_sha256: sha256_hex(leaf),
+            chain,
+        })
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientCertificate
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Self>()
+            .cloned()
+            .ok_or((StatusCode::UNAUTHORIZED, "Client certificate required"))
+    }
+}
+
+fn format_general_name(name: &GeneralName) -> Option<String> {
+    match name {
+        GeneralName::DNSName(dns) => Some(format!("DNS:{dns}")),
+        GeneralName::RFC822Name(email) => Some(format!("email:{email}")),
+        GeneralName::URI(uri) => Some(format!("URI:{uri}")),
+        GeneralName::IPAddress(bytes) => format_ip(bytes).map(|ip| format!("IP:{ip}")),
+        _ => None,
+    }
+}
+
+pub(super) fn format_ip(bytes: &[u8]) -> Option<std::net::IpAddr> {
+    match bytes.len() {
+        4 => <[u8; 4]>::try_from(bytes).ok().map(Into::into),
+        16 => <[u8; 16]>::try_from(bytes).ok().map(Into::into),
+        _ => None,
+    }
+}
+
+pub(super) fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha256_hex_of_empty_input() {
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn general_names_are_prefixed() {
+        assert_eq!(
+            format_general_name(&GeneralName::DNSName("svc.internal")),
+            Some("DNS:svc.internal".into())
+        );
+        assert_eq!(
+            format_general_name(&GeneralName::IPAddress(&[10, 0, 0, 1])),
+            Some("IP:10.0.0.1".into())
+        );
+    }
+}
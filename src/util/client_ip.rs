@@ -0,0 +1,256 @@
+use std::net::{IpAddr, SocketAddr};
+
+use ipnet::IpNet;
+
+use crate::{
+    axum::{
+        async_trait,
+        extract::{ConnectInfo, FromRequestParts},
+        http::{header::FORWARDED, request::Parts, HeaderMap, HeaderName, StatusCode},
+    },
+    util::listener::PeerAddr,
+};
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_REAL_IP: HeaderName = HeaderName::from_static("x-real-ip");
+
+/// The reverse proxies whose forwarding headers are believed, from
+/// `website.trusted_proxies`.  The server adds it to every request.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    #[must_use]
+    pub const fn new(networks: Vec<IpNet>) -> Self {
+        Self(networks)
+    }
+
+    #[must_use]
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.0.iter().any(|net| net.contains(&ip))
+    }
+}
+
+/// The IP address of the client which made a request.
+///
+/// This is the connection's peer address unless the peer is a trusted proxy, in which case
+/// the `Forwarded`, `X-Forwarded-For` or `X-Real-IP` header is followed back to the first
+/// address which is not a trusted proxy.  Unix domain socket peers are treated as trusted
+/// proxies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+impl ClientIp {
+    /// Resolve the client address of a request from `peer` with `headers`.  Returns `None`
+    /// for a unix domain socket peer which did not forward a client address.
+    #[must_use]
+    pub fn resolve(peer: &PeerAddr, headers: &HeaderMap, trusted: &TrustedProxies) -> Option<Self> {
+        let peer_ip = match peer {
+            PeerAddr::Tcp(addr) => Some(addr.ip().to_canonical()),
+            PeerAddr::Unix(_) => None,
+        };
+        if let Some(ip) = peer_ip.filter(|ip| !trusted.contains(ip)) {
+            return Some(Self(ip));
+        }
+
+        let mut client = peer_ip;
+        for hop in forwarded_for(headers).into_iter().rev() {
+            // Obfuscated and unknown identifiers end the chain of addresses we can follow.
+            let Some(ip) = hop else {
+                break;
+            };
+            client = Some(ip);
+            if !trusted.contains(&ip) {
+                break;
+            }
+        }
+
+        client.map(Self)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let unavailable = (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Client address unavailable",
+        );
+
+        let ConnectInfo(peer) = parts
+            .extensions
+            .get::<ConnectInfo<PeerAddr>>()
+            .ok_or(unavailable)?;
+        let trusted = parts
+            .extensions
+            .get::<TrustedProxies>()
+            .cloned()
+            .unwrap_or_default();
+
+        Self::resolve(peer, &parts.headers, &trusted).ok_or(unavailable)
+    }
+}
+
+/// The client addresses proxies appended to the request, nearest client first.  Entries
+/// which are not IP addresses are `None`.
+fn forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let values = |name| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect::<Vec<_>>()
+    };
+
+    let forwarded = values(FORWARDED);
+    if !forwarded.is_empty() {
+        return forwarded
+            .into_iter()
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, node)| parse_node(node))
+            })
+            .collect();
+    }
+
+    let forwarded_for = values(X_FORWARDED_FOR);
+    if !forwarded_for.is_empty() {
+        return forwarded_for.into_iter().map(parse_node).collect();
+    }
+
+    values(X_REAL_IP).into_iter().map(parse_node).collect()
+}
+
+/// Parse an address as proxies write it: a bare IP, `ip:port`, or a bracketed IPv6
+/// address with an optional port, optionally quoted.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    let ip = match node.strip_prefix('[') {
+        Some(bracketed) => bracketed.split_once(']')?.0.parse().ok()?,
+        None => node
+            .parse::<IpAddr>()
+            .or_else(|_| node.parse::<SocketAddr>().map(|addr| addr.ip()))
+            .ok()?,
+    };
+    Some(ip.to_canonical())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{axum::http::HeaderValue, util::listener::UnixPeer};
+
+    fn trusted() -> TrustedProxies {
+        TrustedProxies::new(vec![
+            "10.0.0.0/8".parse().unwrap(),
+            "2001:db8:ffff::/48".parse().unwrap(),
+        ])
+    }
+
+    fn resolve(peer: &str, headers: &[(HeaderName, &str)]) -> Option<IpAddr> {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(name, HeaderValue::from_str(value).unwrap());
+        }
+        ClientIp::resolve(&PeerAddr::Tcp(peer.parse().unwrap()), &map, &trusted())
+            .map(|ClientIp(ip)| ip)
+    }
+
+    fn ip(ip: &str) -> Option<IpAddr> {
+        ip.parse().ok()
+    }
+
+    #[test]
+    fn untrusted_peer_headers_are_ignored() {
+        assert_eq!(
+            resolve("192.0.2.1:5000", &[(X_FORWARDED_FOR, "198.51.100.7")]),
+            ip("192.0.2.1")
+        );
+    }
+
+    #[test]
+    fn trusted_peer_without_headers_is_the_client() {
+        assert_eq!(resolve("10.0.0.1:5000", &[]), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn x_forwarded_for_skips_trusted_hops() {
+        assert_eq!(
+            resolve(
+                "10.0.0.1:5000",
+                &[
+                    (X_FORWARDED_FOR, "203.0.113.9, 198.51.100.7"),
+                    (X_FORWARDED_FOR, "10.0.0.2"),
+                ],
+            ),
+            ip("198.51.100.7")
+        );
+    }
+
+    #[test]
+    fn forwarded_takes_precedence_and_parses_nodes() {
+        assert_eq!(
+            resolve(
+                "10.0.0.1:5000",
+                &[
+                    (
+                        FORWARDED,
+                        r#"for=198.51.100.7;proto=https, For="[2001:db8:ffff::1]:4711""#,
+                    ),
+                    (X_FORWARDED_FOR, "203.0.113.9"),
+                ],
+            ),
+            ip("198.51.100.7")
+        );
+    }
+
+    #[test]
+    fn unknown_node_ends_the_chain() {
+        assert_eq!(
+            resolve(
+                "10.0.0.1:5000",
+                &[(FORWARDED, "for=198.51.100.7, for=unknown, for=10.0.0.3")]
+            ),
+            ip("10.0.0.3")
+        );
+    }
+
+    #[test]
+    fn x_real_ip_is_used_last() {
+        assert_eq!(
+            resolve("10.0.0.1:5000", &[(X_REAL_IP, "198.51.100.7")]),
+            ip("198.51.100.7")
+        );
+        assert_eq!(
+            resolve(
+                "[::ffff:10.0.0.1]:5000",
+                &[(X_REAL_IP, "::ffff:198.51.100.7")]
+            ),
+            ip("198.51.100.7")
+        );
+    }
+
+    #[test]
+    fn unix_peer_needs_a_forwarded_address() {
+        let unix = PeerAddr::Unix(UnixPeer::default());
+        let mut headers = HeaderMap::new();
+        assert_eq!(ClientIp::resolve(&unix, &headers, &trusted()), None);
+
+        headers.insert(X_FORWARDED_FOR, HeaderValue::from_static("198.51.100.7"));
+        assert_eq!(
+            ClientIp::resolve(&unix, &headers, &trusted()),
+            Some(ClientIp("198.51.100.7".parse().unwrap()))
+        );
+    }
+}
@@ -1,6 +1,9 @@
 #![allow(clippy::module_name_repetitions)]
 
-use std::net::IpAddr;
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+};
 
 use grafton_config::{GraftonConfig, GraftonConfigProvider, TokenExpandingConfig};
 
@@ -8,6 +11,7 @@ use crate::{Error, ServerConfigProvider, Verbosity};
 
 use {
     derivative::Derivative,
+    ipnet::IpNet,
     serde::{Deserialize, Serialize},
     strum::{Display, EnumString, VariantNames},
     url::Url,
@@ -40,9 +44,40 @@ pub struct Website {
 
     #[derivative(Default(value = "false"))]
     pub public_ssl_enabled: bool,
+
+    #[derivative(Default(value = "30"))]
+    pub shutdown_grace_period_secs: u64,
+
+    #[derivative(Default)]
+    pub http_mode: HttpMode,
+
+    #[derivative(Default)]
+    pub redirect_status: RedirectStatus,
+
+    #[derivative(Default)]
+    pub hsts: HstsConfig,
+
+    #[derivative(Default)]
+    pub timeouts: TimeoutConfig,
+
+    #[derivative(Default)]
+    pub connection_limits: ConnectionLimitConfig,
+
+    /// Networks of reverse proxies trusted to report the client address in `Forwarded`,
+    /// `X-Forwarded-For` or `X-Real-IP` headers, for the `ClientIp` extractor.
+    #[derivative(Default)]
+    pub trusted_proxies: Vec<IpNet>,
+
+    /// Explicit listeners.  When empty, listeners are derived from `bind_address`,
+    /// `bind_ports`, `bind_ssl_config` and `http_mode`.
+    #[derivative(Default)]
+    pub listeners: Vec<ListenerConfig>,
+    #[derivative(Default)]
+    pub acme: AcmeConfig,
 }
 
 impl Website {
+    #[must_use]
     pub fn public_server_url(&self) -> String {
         let (protocol, port) = self.get_protocol_and_port();
         match self.format_url(protocol, port) {
@@ -54,6 +89,7 @@ impl Website {
         }
     }
 
+    #[must_use]
     pub fn format_public_server_url(&self, path: &str) -> String {
         let url = self.public_server_url();
         format!(
@@ -63,6 +99,60 @@ impl Website {
         )
     }
 
+    /// The listeners to bind, either as configured or derived from the single-address settings.
+    #[must_use]
+    pub fn effective_listeners(&self) -> Vec<ListenerConfig> {
+        if !self.listeners.is_empty() {
+            return self.listeners.clone();
+        }
+
+        let mut listeners = Vec::new();
+
+        if self.bind_ssl_config.enabled {
+            listeners.push(ListenerConfig {
+                name: Some("https".into()),
+                address: self.bind_address,
+                port: self.bind_ports.https,
+                tls: true,
+                ..Default::default()
+            });
+        }
+
+        if !self.bind_ssl_config.enabled || self.http_mode != HttpMode::Disabled {
+            listeners.push(ListenerConfig {
+                name: Some("http".into()),
+                address: self.bind_address,
+                port: self.bind_ports.http,
+                tls: false,
+                ..Default::default()
+            });
+        }
+
+        listeners
+    }
+
+    /// The names the ACME certificate is requested for.
+    #[must_use]
+    pub fn acme_domains(&self) -> Vec<String> {
+        if self.acme.domains.is_empty() {
+            vec![self.public_hostname.clone()]
+        } else {
+            self.acme.domains.clone()
+        }
+    }
+
+    /// The public HTTPS URL, used as the target when redirecting plain HTTP requests.
+    #[must_use]
+    pub fn public_https_url(&self) -> String {
+        match self.format_url("https", self.public_ports.https) {
+            Ok(url) => url,
+            Err(err) => {
+                eprintln!("Error generating URL: {err}");
+                String::new()
+            }
+        }
+    }
+
     const fn get_protocol_and_port(&self) -> (&str, u16) {
         if self.public_ssl_enabled {
             ("https", self.public_ports.https)
@@ -71,6 +161,7 @@ impl Website {
         }
     }
 
+    #[must_use]
     pub fn is_default_port(protocol: &str, port: u16) -> bool {
         let defaults = Port::default();
         match protocol {
@@ -119,13 +210,534 @@ pub enum SameSiteConfig {
 #[derive(Debug, Serialize, Deserialize, Derivative, Clone)]
 #[derivative(Default)]
 #[serde(default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct ListenerConfig {
+    /// Optional name used in logs and when reporting bound sockets.
+    #[derivative(Default)]
+    pub name: Option<String>,
+
+    #[derivative(Default)]
+    pub kind: ListenerKind,
+
+    #[derivative(Default(value = "\"127.0.0.1\".parse().unwrap()"))]
+    pub address: IpAddr,
+
+    #[derivative(Default(value = "80"))]
+    pub port: u16,
+
+    /// Terminate TLS on this listener using `bind_ssl_config`.
+    #[derivative(Default(value = "false"))]
+    pub tls: bool,
+
+    /// Also serve HTTP/3 over QUIC on the same port using UDP, advertised to clients with
+    /// an `Alt-Svc` header.  Requires `tls` and the `http3` feature.
+    #[derivative(Default(value = "false"))]
+    pub http3: bool,
+
+    /// HTTP versions accepted on this listener.  `http2` on a plaintext listener serves
+    /// cleartext HTTP/2 (h2c) to clients with prior knowledge.
+    #[derivative(Default)]
+    pub protocol: HttpProtocol,
+
+    #[derivative(Default)]
+    pub http1: Http1Config,
+
+    #[derivative(Default)]
+    pub http2: Http2Config,
+
+    /// Expect every connection to start with a PROXY protocol v1 or v2 header from a load
+    /// balancer, and report the client address it carries instead of the load balancer's.
+    #[derivative(Default(value = "false"))]
+    pub proxy_protocol: bool,
+
+    /// Networks allowed to send PROXY protocol headers, e.g. `10.0.0.0/8`.  Connections
+    /// from any other address are closed.  Required for TCP listeners with
+    /// `proxy_protocol` set.
+    #[derivative(Default)]
+    pub proxy_protocol_trusted: Vec<IpNet>,
+
+    /// Restrict IPv6 listeners to IPv6 traffic so that `::` and `0.0.0.0` can be bound
+    /// on the same port.
+    #[derivative(Default(value = "true"))]
+    pub ipv6_only: bool,
+
+    #[derivative(Default)]
+    pub tcp: TcpSocketConfig,
+
+    /// Bind several sockets to the address with `SO_REUSEPORT`, each with its own accept
+    /// loop, so the kernel spreads new connections across them.  Only supported for TCP
+    /// listeners on unix, and not for inherited sockets.
+    #[derivative(Default(value = "false"))]
+    pub reuse_port: bool,
+
+    /// Number of sockets bound when `reuse_port` is set, defaulting to the number of
+    /// runtime worker threads.
+    #[derivative(Default)]
+    pub acceptors: Option<usize>,
+
+    /// Filesystem path of a unix listener.
+    #[derivative(Default)]
+    pub path: Option<PathBuf>,
+
+    /// Permission bits applied to a unix socket file, e.g. `0o660`.
+    #[derivative(Default)]
+    pub socket_mode: Option<u32>,
+
+    /// Numeric user id to own a unix socket file.
+    #[derivative(Default)]
+    pub socket_owner: Option<u32>,
+
+    /// Numeric group id to own a unix socket file.
+    #[derivative(Default)]
+    pub socket_group: Option<u32>,
+
+    /// Remove a socket file left behind by a previous process before binding.
+    #[derivative(Default(value = "true"))]
+    pub remove_stale_socket: bool,
+
+    /// Adopt a socket passed in by systemd socket activation instead of binding, falling
+    /// back to binding when the process was not socket activated.
+    #[derivative(Default)]
+    pub inherit_fd: Option<InheritFd>,
+}
+
+/// Options for TCP listening sockets and the connections accepted from them.
+#[derive(Debug, Serialize, Deserialize, Derivative, Clone)]
+#[derivative(Default)]
+#[serde(default)]
+pub struct TcpSocketConfig {
+    /// Disable Nagle's algorithm on accepted connections, sending small responses at once.
+    #[derivative(Default(value = "true"))]
+    pub nodelay: bool,
+    /// Maximum number of connections queued by the kernel while waiting to be accepted.
+    #[derivative(Default(value = "1024"))]
+    pub backlog: u32,
+    /// Send keepalive probes on accepted connections which have been silent this long, to
+    /// detect dead peers.  Disabled when unset.
+    #[derivative(Default)]
+    pub keepalive_secs: Option<u64>,
+    /// Size of the kernel send buffer (`SO_SNDBUF`) in bytes, inherited by accepted
+    /// connections.
+    #[derivative(Default)]
+    pub send_buffer_size: Option<usize>,
+    /// Size of the kernel receive buffer (`SO_RCVBUF`) in bytes, inherited by accepted
+    /// connections.
+    #[derivative(Default)]
+    pub recv_buffer_size: Option<usize>,
+}
+
+/// Selects an inherited socket either by its position or by its `FileDescriptorName=`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum InheritFd {
+    Index(usize),
+    Name(String),
+}
+
+impl ListenerConfig {
+    #[must_use]
+    pub const fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+
+    #[must_use]
+    pub fn display_name(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+
+        match (&self.kind, &self.path) {
+            (ListenerKind::Unix, Some(path)) => format!("unix:{}", path.display()),
+            _ => self.socket_addr().to_string(),
+        }
+    }
+}
+
+#[derive(
+    Default, Display, EnumString, VariantNames, Debug, Serialize, Deserialize, Clone, PartialEq, Eq,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ListenerKind {
+    #[default]
+    Tcp,
+    /// A unix domain socket at `path`.
+    Unix,
+}
+
+/// HTTP versions served on a listener.
+#[derive(
+    Default, Display, EnumString, VariantNames, Debug, Serialize, Deserialize, Clone, PartialEq, Eq,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum HttpProtocol {
+    /// HTTP/1.1 and HTTP/2, chosen through ALPN or by the connection preface.
+    #[default]
+    Auto,
+    /// HTTP/1.1 only.
+    Http1,
+    /// HTTP/2 only.
+    Http2,
+}
+
+impl HttpProtocol {
+    /// Whether a connection may negotiate the ALPN protocol `id`.
+    #[must_use]
+    pub fn allows_alpn(&self, id: &[u8]) -> bool {
+        match self {
+            Self::Auto => true,
+            Self::Http1 => id != b"h2",
+            Self::Http2 => id != b"http/1.1",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Derivative, Clone)]
+#[derivative(Default)]
+#[serde(default)]
+pub struct Http1Config {
+    /// Keep connections open between requests.
+    #[derivative(Default(value = "true"))]
+    pub keep_alive: bool,
+    /// Maximum number of request headers, defaulting to hyper's limit of 100.
+    #[derivative(Default)]
+    pub max_headers: Option<usize>,
+    /// Overrides `website.timeouts.header_read_secs` for this listener.
+    #[derivative(Default)]
+    pub header_read_timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Derivative, Clone)]
+#[derivative(Default)]
+#[serde(default)]
+pub struct Http2Config {
+    /// Maximum number of concurrent streams per connection, defaulting to 200.
+    #[derivative(Default)]
+    pub max_concurrent_streams: Option<u32>,
+    /// Initial flow control window of each stream in bytes.
+    #[derivative(Default)]
+    pub initial_stream_window_size: Option<u32>,
+    /// Initial flow control window of each connection in bytes.
+    #[derivative(Default)]
+    pub initial_connection_window_size: Option<u32>,
+    /// Size the flow control windows from the measured bandwidth-delay product, overriding
+    /// the initial window sizes.
+    #[derivative(Default(value = "false"))]
+    pub adaptive_window: bool,
+    /// Send a keep-alive ping after this many idle seconds.  Disabled when unset.
+    #[derivative(Default)]
+    pub keep_alive_interval_secs: Option<u64>,
+    /// Close the connection when a keep-alive ping is not acknowledged in time.
+    #[derivative(Default(value = "20"))]
+    pub keep_alive_timeout_secs: u64,
+}
+
+/// What plain HTTP listeners do while HTTPS is enabled.
+#[derive(
+    Default, Display, EnumString, VariantNames, Debug, Serialize, Deserialize, Clone, PartialEq, Eq,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum HttpMode {
+    /// Serve the same router as the HTTPS listener.
+    Serve,
+    /// Redirect every request to the public HTTPS URL.
+    #[default]
+    Redirect,
+    /// Do not listen on the HTTP port.
+    Disabled,
+}
+
+#[derive(
+    Default, Display, EnumString, VariantNames, Debug, Serialize, Deserialize, Clone, PartialEq, Eq,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum RedirectStatus {
+    /// 301 Moved Permanently
+    MovedPermanently,
+    /// 308 Permanent Redirect
+    #[default]
+    PermanentRedirect,
+}
+
+#[derive(Debug, Serialize, Deserialize, Derivative, Clone)]
+#[derivative(Default)]
+#[serde(default)]
+pub struct HstsConfig {
+    #[derivative(Default(value = "false"))]
+    pub enabled: bool,
+    #[derivative(Default(value = "31_536_000"))]
+    pub max_age_secs: u64,
+    #[derivative(Default(value = "false"))]
+    pub include_subdomains: bool,
+    #[derivative(Default(value = "false"))]
+    pub preload: bool,
+}
+
+/// Limits which stop slow or idle clients from holding connections open.  Connections
+/// closed by each limit are counted in the server stats.
+#[derive(Debug, Serialize, Deserialize, Derivative, Clone)]
+#[derivative(Default)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    /// Close connections which have not completed the TLS handshake in time.
+    #[derivative(Default(value = "Some(10)"))]
+    pub tls_handshake_secs: Option<u64>,
+    /// Close HTTP/1 connections which have not sent a complete request head in time,
+    /// including while waiting for the next request on a kept-alive connection.
+    #[derivative(Default(value = "Some(30)"))]
+    pub header_read_secs: Option<u64>,
+    /// Close connections which have no request in progress and have sent or received
+    /// nothing for this long.
+    #[derivative(Default(value = "Some(60)"))]
+    pub idle_secs: Option<u64>,
+    /// Ask connections which have been open this long to close once their current
+    /// requests complete.
+    #[derivative(Default)]
+    pub max_connection_lifetime_secs: Option<u64>,
+}
+
+/// Caps on the connections open at once across every listener, so a client opening
+/// thousands of sockets cannot exhaust the server.
+#[derive(Debug, Serialize, Deserialize, Derivative, Clone)]
+#[derivative(Default)]
+#[serde(default)]
+pub struct ConnectionLimitConfig {
+    /// Maximum connections open at once.  Unlimited when unset.
+    #[derivative(Default)]
+    pub max_connections: Option<usize>,
+    /// Maximum connections open at once from one client IP address, taken from the PROXY
+    /// protocol header when the listener expects one.  Connections over this limit are
+    /// always closed.  Unlimited when unset.
+    #[derivative(Default)]
+    pub max_connections_per_ip: Option<usize>,
+    /// What listeners do while `max_connections` are open.
+    #[derivative(Default)]
+    pub on_limit: ConnectionLimitAction,
+}
+
+/// What listeners do while the server is at its connection limit.
+#[derive(
+    Default, Display, EnumString, VariantNames, Debug, Serialize, Deserialize, Clone, PartialEq, Eq,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionLimitAction {
+    /// Stop accepting until a connection closes, leaving new clients queued in the
+    /// listen backlog.
+    #[default]
+    Pause,
+    /// Keep accepting, closing each new connection at once.
+    Close,
+}
+
+impl HstsConfig {
+    /// The `Strict-Transport-Security` header value for this config.
+    #[must_use]
+    pub fn header_value(&self) -> String {
+        let mut value = format!("max-age={}", self.max_age_secs);
+        if self.include_subdomains {
+            value.push_str("; includeSubDomains");
+        }
+        if self.preload {
+            value.push_str("; preload");
+        }
+        value
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Derivative, Clone)]
+#[derivative(Default)]
+#[serde(default)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct SslConfig {
     #[derivative(Default(value = "false"))]
     pub enabled: bool,
+    /// The default certificate, served when the client's server name matches none of
+    /// `certificates`.
     #[derivative(Default(value = "\"config/cert.pem\".into()"))]
     pub cert_path: String,
     #[derivative(Default(value = "\"config/key.pem\".into()"))]
     pub key_path: String,
+    /// For local development: when neither `cert_path` nor `key_path` exists, generate a
+    /// self-signed certificate for `public_hostname` and the loopback addresses and save
+    /// it there.
+    #[derivative(Default(value = "false"))]
+    pub generate_self_signed: bool,
+    /// Additional certificates, selected by the server name the client sends (SNI).
+    #[derivative(Default)]
+    pub certificates: Vec<CertificateConfig>,
+    /// Reject handshakes whose server name matches none of `certificates` instead of
+    /// serving the default certificate.  Clients which send no server name still get
+    /// the default certificate.
+    #[derivative(Default(value = "false"))]
+    pub sni_strict: bool,
+    /// Check the certificate and key files for changes this often, reloading them when
+    /// they change.  Zero disables watching.
+    #[derivative(Default(value = "0"))]
+    pub reload_interval_secs: u64,
+    /// Reload the certificate and key when the process receives SIGHUP.
+    #[derivative(Default(value = "false"))]
+    pub reload_on_sighup: bool,
+    /// Whether clients must present a certificate signed by `client_ca_path`.
+    #[derivative(Default)]
+    pub client_auth: ClientAuth,
+    /// PEM bundle of CAs trusted to sign client certificates.
+    #[derivative(Default)]
+    pub client_ca_path: Option<String>,
+    /// Certificate revocation lists checked against client certificates.
+    #[derivative(Default)]
+    pub client_crl_paths: Vec<String>,
+    /// The oldest TLS version clients may negotiate.
+    #[derivative(Default)]
+    pub min_version: TlsVersion,
+    /// Cipher suites to offer, by IANA name such as `TLS13_AES_256_GCM_SHA384` or
+    /// `TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256`.  Empty uses the provider's defaults.
+    #[derivative(Default)]
+    pub cipher_suites: Vec<String>,
+    /// Key exchange groups to offer, such as `X25519` or `secp256r1`.  Empty uses the
+    /// provider's defaults.
+    #[derivative(Default)]
+    pub kx_groups: Vec<String>,
+    /// Application protocols advertised through ALPN, in order of preference.  Supported
+    /// values are `h2` and `http/1.1`.
+    #[derivative(Default(value = "vec![\"h2\".into(), \"http/1.1\".into()]"))]
+    pub alpn_protocols: Vec<String>,
+    /// Issue stateless session tickets so clients can resume sessions without server-side
+    /// state.
+    #[derivative(Default(value = "false"))]
+    pub session_tickets: bool,
+    /// Number of sessions kept in memory for stateful resumption.  Zero disables it.
+    #[derivative(Default(value = "256"))]
+    pub session_cache_size: usize,
+    /// Log a warning when a loaded certificate expires within each of these many days.
+    #[derivative(Default(value = "vec![30, 14, 7, 1]"))]
+    pub expiry_warning_days: Vec<u64>,
+    /// How often to check the expiry of loaded certificates.  Zero disables the check.
+    #[derivative(Default(value = "3600"))]
+    pub expiry_check_interval_secs: u64,
+    /// A DER encoded OCSP response to staple to the default certificate, read again on
+    /// each refresh.  Like fetched responses it is only stapled when signed for the
+    /// certificate's issuer, which must follow it in `cert_path`.
+    #[derivative(Default)]
+    pub ocsp_response_path: Option<String>,
+    /// Fetch OCSP responses to staple from the responder named in each certificate.
+    #[derivative(Default(value = "false"))]
+    pub ocsp_fetch: bool,
+    /// How often to reload or fetch OCSP responses, which must be greater than zero.
+    #[derivative(Default(value = "3600"))]
+    pub ocsp_refresh_interval_secs: u64,
+}
+
+#[derive(
+    Default, Display, EnumString, VariantNames, Debug, Serialize, Deserialize, Clone, PartialEq, Eq,
+)]
+pub enum TlsVersion {
+    #[default]
+    #[strum(serialize = "1.2")]
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[strum(serialize = "1.3")]
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+/// Obtain and renew the certificate for TLS listeners from an ACME directory such as
+/// Let's Encrypt, instead of reading `cert_path` and `key_path`.
+#[derive(Debug, Serialize, Deserialize, Derivative, Clone)]
+#[derivative(Default)]
+#[serde(default)]
+pub struct AcmeConfig {
+    #[derivative(Default(value = "false"))]
+    pub enabled: bool,
+    #[derivative(Default(value = "\"https://acme-v02.api.letsencrypt.org/directory\".into()"))]
+    pub directory_url: String,
+    /// Must be set to agree to the directory's terms of service when registering.
+    #[derivative(Default(value = "false"))]
+    pub terms_of_service_agreed: bool,
+    /// Contact URLs for the account, e.g. `mailto:admin@example.com`.
+    #[derivative(Default)]
+    pub contact: Vec<String>,
+    /// Names to request the certificate for.  Empty uses `public_hostname`.
+    #[derivative(Default)]
+    pub domains: Vec<String>,
+    #[derivative(Default)]
+    pub challenge: AcmeChallenge,
+    /// Where the account credentials, certificate and key are kept between runs.
+    #[derivative(Default(value = "\"config/acme\".into()"))]
+    pub cache_dir: PathBuf,
+    /// Renew the certificate once it expires within this many days.
+    #[derivative(Default(value = "30"))]
+    pub renew_before_days: u64,
+    /// How often to check whether the certificate needs renewing.
+    #[derivative(Default(value = "43_200"))]
+    pub check_interval_secs: u64,
+    /// PEM bundle of CAs trusted for the directory's HTTPS endpoint, such as pebble's
+    /// `pebble.minica.pem`.  Defaults to the platform's trusted roots.
+    #[derivative(Default)]
+    pub ca_roots_path: Option<String>,
+}
+
+impl AcmeConfig {
+    #[must_use]
+    pub fn cert_path(&self) -> PathBuf {
+        self.cache_dir.join("certificate.pem")
+    }
+
+    #[must_use]
+    pub fn key_path(&self) -> PathBuf {
+        self.cache_dir.join("private_key.pem")
+    }
+}
+
+/// How the ACME server validates control of each domain.
+#[derive(
+    Default, Display, EnumString, VariantNames, Debug, Serialize, Deserialize, Clone, PartialEq, Eq,
+)]
+pub enum AcmeChallenge {
+    /// Answer `/.well-known/acme-challenge/` requests on the plain HTTP listener, which
+    /// must be reachable on port 80.
+    #[default]
+    #[strum(serialize = "http-01")]
+    #[serde(rename = "http-01")]
+    Http01,
+    /// Answer `acme-tls/1` handshakes on the TLS listener, which must be reachable on
+    /// port 443.
+    #[strum(serialize = "tls-alpn-01")]
+    #[serde(rename = "tls-alpn-01")]
+    TlsAlpn01,
+}
+
+/// A certificate served to clients which ask for one of its server names.
+#[derive(Debug, Serialize, Deserialize, Derivative, Clone, PartialEq, Eq)]
+#[derivative(Default)]
+#[serde(default)]
+pub struct CertificateConfig {
+    /// Host names such as `example.com` or `*.example.com`.  A wildcard matches exactly
+    /// one label.  When empty, the DNS names in the certificate are used.
+    #[derivative(Default)]
+    pub server_names: Vec<String>,
+    #[derivative(Default)]
+    pub cert_path: String,
+    #[derivative(Default)]
+    pub key_path: String,
+}
+
+#[derive(
+    Default, Display, EnumString, VariantNames, Debug, Serialize, Deserialize, Clone, PartialEq, Eq,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ClientAuth {
+    /// Do not ask clients for a certificate.
+    #[default]
+    None,
+    /// Verify a client certificate when one is presented, but allow anonymous clients.
+    Optional,
+    /// Reject clients which do not present a valid certificate.
+    Required,
 }
 
 #[derive(Debug, Serialize, Deserialize, Derivative, Clone)]
@@ -344,6 +956,170 @@ mod tests {
         assert_eq!(url, "https://example.com");
     }
 
+    #[test]
+    fn public_https_url_uses_https_port_when_ssl_disabled() {
+        let website = create_website(false, 8080, 8443, "example.com");
+        assert_eq!(website.public_https_url(), "https://example.com:8443");
+    }
+
+    #[test]
+    fn hsts_header_value_with_all_directives() {
+        let hsts = HstsConfig {
+            enabled: true,
+            max_age_secs: 600,
+            include_subdomains: true,
+            preload: true,
+        };
+        assert_eq!(
+            hsts.header_value(),
+            "max-age=600; includeSubDomains; preload"
+        );
+    }
+
+    #[test]
+    fn hsts_header_value_default() {
+        assert_eq!(HstsConfig::default().header_value(), "max-age=31536000");
+    }
+
+    #[test]
+    fn acme_settings_deserialize() {
+        let website: Website = serde_json::from_str(
+            r#"{"public_hostname": "example.com", "acme": {"enabled": true, "challenge": "tls-alpn-01"}}"#,
+        )
+        .unwrap();
+
+        assert!(website.acme.enabled);
+        assert_eq!(website.acme.challenge, AcmeChallenge::TlsAlpn01);
+        assert_eq!(website.acme.renew_before_days, 30);
+        assert_eq!(website.acme_domains(), ["example.com"]);
+    }
+
+    #[test]
+    fn ssl_protocol_settings_deserialize() {
+        let ssl: SslConfig = serde_json::from_str(
+            r#"{"min_version": "1.3", "alpn_protocols": ["h2"], "session_tickets": true}"#,
+        )
+        .unwrap();
+
+        assert_eq!(ssl.min_version, TlsVersion::Tls13);
+        assert_eq!(ssl.alpn_protocols, ["h2"]);
+        assert!(ssl.session_tickets);
+        assert_eq!(ssl.session_cache_size, 256);
+        assert_eq!(ssl.ocsp_response_path, None);
+        assert!(!ssl.ocsp_fetch);
+        assert_eq!(ssl.ocsp_refresh_interval_secs, 3600);
+        assert_eq!(SslConfig::default().alpn_protocols, ["h2", "http/1.1"]);
+    }
+
+    #[test]
+    fn effective_listeners_default_is_plain_http() {
+        let listeners = Website::default().effective_listeners();
+        assert_eq!(listeners.len(), 1);
+        assert!(!listeners[0].tls);
+        assert_eq!(listeners[0].socket_addr(), "127.0.0.1:80".parse().unwrap());
+    }
+
+    #[test]
+    fn effective_listeners_with_ssl_binds_both_ports() {
+        let mut website = Website::default();
+        website.bind_ssl_config.enabled = true;
+
+        let listeners = website.effective_listeners();
+        assert_eq!(listeners.len(), 2);
+        assert!(listeners[0].tls);
+        assert_eq!(listeners[0].port, 443);
+        assert!(!listeners[1].tls);
+        assert_eq!(listeners[1].port, 80);
+    }
+
+    #[test]
+    fn effective_listeners_with_ssl_and_http_disabled() {
+        let mut website = Website::default();
+        website.bind_ssl_config.enabled = true;
+        website.http_mode = HttpMode::Disabled;
+
+        let listeners = website.effective_listeners();
+        assert_eq!(listeners.len(), 1);
+        assert!(listeners[0].tls);
+    }
+
+    #[test]
+    fn effective_listeners_prefers_explicit_list() {
+        let website = Website {
+            listeners: vec![
+                ListenerConfig {
+                    address: "0.0.0.0".parse().unwrap(),
+                    port: 8080,
+                    ..Default::default()
+                },
+                ListenerConfig {
+                    address: "::".parse().unwrap(),
+                    port: 8080,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let listeners = website.effective_listeners();
+        assert_eq!(listeners.len(), 2);
+        assert_eq!(listeners[1].socket_addr(), "[::]:8080".parse().unwrap());
+        assert_eq!(listeners[1].display_name(), "[::]:8080");
+    }
+
+    #[test]
+    fn listeners_deserialize_from_toml_style_json() {
+        let website: Website = serde_json::from_str(
+            r#"{"listeners": [{"name": "public", "address": "::", "port": 8443, "tls": true}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(website.listeners.len(), 1);
+        assert_eq!(website.listeners[0].display_name(), "public");
+        assert!(website.listeners[0].tls);
+        assert!(website.listeners[0].ipv6_only);
+    }
+
+    #[test]
+    fn unix_listener_deserializes_with_socket_options() {
+        let listener: ListenerConfig = serde_json::from_str(
+            r#"{"kind": "unix", "path": "/run/grafton.sock", "socket_mode": 432, "socket_group": 33}"#,
+        )
+        .unwrap();
+
+        assert_eq!(listener.kind, ListenerKind::Unix);
+        assert_eq!(listener.socket_mode, Some(0o660));
+        assert_eq!(listener.socket_group, Some(33));
+        assert!(listener.remove_stale_socket);
+        assert_eq!(listener.display_name(), "unix:/run/grafton.sock");
+    }
+
+    #[test]
+    fn inherit_fd_deserializes_by_index_or_name() {
+        let by_index: ListenerConfig = serde_json::from_str(r#"{"inherit_fd": 1}"#).unwrap();
+        assert_eq!(by_index.inherit_fd, Some(InheritFd::Index(1)));
+
+        let by_name: ListenerConfig = serde_json::from_str(r#"{"inherit_fd": "https"}"#).unwrap();
+        assert_eq!(by_name.inherit_fd, Some(InheritFd::Name("https".into())));
+    }
+
+    #[test]
+    fn listener_protocol_settings_deserialize() {
+        let listener: ListenerConfig = serde_json::from_str(
+            r#"{"protocol": "http2", "http2": {"max_concurrent_streams": 500, "keep_alive_interval_secs": 10}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(listener.protocol, HttpProtocol::Http2);
+        assert_eq!(listener.http2.max_concurrent_streams, Some(500));
+        assert_eq!(listener.http2.keep_alive_interval_secs, Some(10));
+        assert_eq!(listener.http2.keep_alive_timeout_secs, 20);
+        assert!(listener.http1.keep_alive);
+        assert_eq!(listener.http1.header_read_timeout_secs, None);
+        assert!(!HttpProtocol::Http2.allows_alpn(b"http/1.1"));
+        assert!(HttpProtocol::Http1.allows_alpn(b"acme-tls/1"));
+    }
+
     #[test]
     fn test_default_website_config() {
         let default_website = Website::default();
@@ -1,219 +1,520 @@
 use std::{
-    fs::File,
-    io::{self, BufReader},
-    net::SocketAddr,
-    path::Path,
+    future,
     sync::Arc,
+    time::{Duration, Instant},
 };
 
 use {
     askama_axum::IntoResponse,
     hyper::body::Incoming,
     hyper_util::{
-        rt::{TokioExecutor, TokioIo},
+        rt::{TokioExecutor, TokioIo, TokioTimer},
         server::conn::auto::Builder as AutoBuilder,
     },
-    rustls_pemfile::{certs, pkcs8_private_keys},
-    rustls_pki_types::{CertificateDer, PrivateKeyDer},
-    tokio::net::TcpListener,
-    tokio_rustls::{rustls::ServerConfig, TlsAcceptor},
+    tokio::{
+        io::{AsyncRead, AsyncWrite},
+        task::JoinSet,
+    },
+    tokio_rustls::TlsAcceptor,
+    tokio_util::sync::CancellationToken,
     tower::ServiceExt,
+    tower_http::set_header::SetResponseHeaderLayer,
 };
 
 use crate::{
-    axum::{extract::Request, Router},
-    tracing::{debug, error},
-    util::config::SslConfig,
+    axum::{
+        body::Body,
+        extract::{ConnectInfo, Request},
+        http::{
+            header::{LOCATION, STRICT_TRANSPORT_SECURITY},
+            uri::PathAndQuery,
+            Extensions, HeaderValue, StatusCode, Uri,
+        },
+        Router,
+    },
+    tracing::{debug, error, warn},
+    util::{
+        accept::{AcceptErrorKind, Backoff, ErrorLogLimiter},
+        activity::{Activity, ActivityIo, TrackedBody},
+        client_cert::ClientCertificate,
+        config::{HstsConfig, HttpProtocol, ListenerConfig, RedirectStatus, TimeoutConfig},
+        limits::{ConnectionLimiter, ConnectionPermit},
+        listener::{Listener, PeerAddr},
+        proxy_protocol::{self, ProxyProtocol},
+        stats::ServerStats,
+        tls::ACME_TLS_ALPN,
+    },
     Error,
 };
 
-fn create_tls_config(ssl_config: &SslConfig) -> Result<ServerConfig, Error> {
-    debug!("Creating TLS Config with SSL Config: {:?}", ssl_config);
+/// Serves HTTP/1 and HTTP/2 on accepted connections.
+pub type ConnectionBuilder = AutoBuilder<TokioExecutor>;
 
-    let certs = load_certs(Path::new(&ssl_config.cert_path))?;
-    let key = load_keys(Path::new(&ssl_config.key_path))?;
-
-    let config = ServerConfig::builder()
-        .with_no_client_auth()
-        .with_single_cert(certs, key)
-        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
-
-    Ok(config)
+/// Settings applied to every connection accepted on a listener.
+pub struct ConnectionSettings {
+    pub builder: ConnectionBuilder,
+    pub proxy_protocol: Option<ProxyProtocol>,
+    pub tls_handshake_timeout: Option<Duration>,
+    pub idle_timeout: Option<Duration>,
+    pub max_lifetime: Option<Duration>,
+    pub limiter: Arc<ConnectionLimiter>,
+    pub stats: Arc<ServerStats>,
 }
 
-fn load_certs(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
-    debug!("Loading certificates from {:?}", path);
+impl ConnectionSettings {
+    #[must_use]
+    pub fn new(
+        config: &ListenerConfig,
+        timeouts: &TimeoutConfig,
+        limiter: Arc<ConnectionLimiter>,
+        stats: Arc<ServerStats>,
+    ) -> Self {
+        Self {
+            builder: connection_builder(config, timeouts),
+            proxy_protocol: config
+                .proxy_protocol
+                .then(|| ProxyProtocol::new(config.proxy_protocol_trusted.clone())),
+            tls_handshake_timeout: timeouts.tls_handshake_secs.map(Duration::from_secs),
+            idle_timeout: timeouts.idle_secs.map(Duration::from_secs),
+            max_lifetime: timeouts
+                .max_connection_lifetime_secs
+                .map(Duration::from_secs),
+            limiter,
+            stats,
+        }
+    }
 
-    let file = open_cert_file(path)?;
-    parse_certs_from_file(file)
-}
+    /// Read the PROXY protocol header of a new connection when the listener expects one,
+    /// returning the client's address and the extensions to add to its requests.  Returns
+    /// `None` when the connection should be closed.
+    async fn accept_proxied<I>(&self, io: &mut I, peer: PeerAddr) -> Option<(PeerAddr, Extensions)>
+    where
+        I: AsyncRead + Unpin,
+    {
+        let mut extensions = Extensions::new();
+        let Some(proxy_protocol) = &self.proxy_protocol else {
+            return Some((peer, extensions));
+        };
 
-fn open_cert_file(path: &Path) -> io::Result<File> {
-    if !path.exists() {
-        error!("Certificate file path does not exist: {:?}", path);
-        return Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"));
-    }
+        if !proxy_protocol.trusts(&peer) {
+            warn!(
+                "Closing connection from {} which is not a trusted proxy",
+                peer
+            );
+            return None;
+        }
 
-    File::open(path).map_err(|e| {
-        error!("Failed to open certificate file: {:?}", e);
-        e
-    })
+        match tokio::time::timeout(
+            proxy_protocol::HEADER_TIMEOUT,
+            proxy_protocol::read_header(io),
+        )
+        .await
+        {
+            Ok(Ok(Some(header))) => {
+                extensions.insert(ConnectInfo(header));
+                Some((PeerAddr::Tcp(header.source), extensions))
+            }
+            Ok(Ok(None)) => Some((peer, extensions)),
+            Ok(Err(e)) => {
+                debug!("Invalid PROXY protocol header from {}: {}", peer, e);
+                None
+            }
+            Err(_) => {
+                debug!("Timed out reading the PROXY protocol header from {}", peer);
+                None
+            }
+        }
+    }
 }
 
-fn parse_certs_from_file(file: File) -> io::Result<Vec<CertificateDer<'static>>> {
-    let mut reader = BufReader::new(file);
-    let mut cert_vec = Vec::new();
+/// A connection builder restricted to the listener's HTTP versions and tuned by its
+/// `http1` and `http2` settings.
+fn connection_builder(config: &ListenerConfig, timeouts: &TimeoutConfig) -> ConnectionBuilder {
+    let mut builder = AutoBuilder::new(TokioExecutor::new());
 
-    while let Some(cert) = read_next_cert(&mut reader)? {
-        cert_vec.push(cert);
+    let http1 = &config.http1;
+    let mut http1_builder = builder.http1();
+    http1_builder
+        .timer(TokioTimer::new())
+        .keep_alive(http1.keep_alive)
+        .header_read_timeout(
+            http1
+                .header_read_timeout_secs
+                .or(timeouts.header_read_secs)
+                .map(Duration::from_secs),
+        );
+    if let Some(max_headers) = http1.max_headers {
+        http1_builder.max_headers(max_headers);
     }
 
-    if cert_vec.is_empty() {
-        error!("No certificates were loaded from the file");
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "no certs found"));
+    let http2 = &config.http2;
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .max_concurrent_streams(http2.max_concurrent_streams)
+        .initial_stream_window_size(http2.initial_stream_window_size)
+        .initial_connection_window_size(http2.initial_connection_window_size)
+        .adaptive_window(http2.adaptive_window)
+        .keep_alive_interval(http2.keep_alive_interval_secs.map(Duration::from_secs))
+        .keep_alive_timeout(Duration::from_secs(http2.keep_alive_timeout_secs));
+
+    match config.protocol {
+        HttpProtocol::Auto => builder,
+        HttpProtocol::Http1 => builder.http1_only(),
+        HttpProtocol::Http2 => builder.http2_only(),
     }
+}
+
+/// A router which redirects every request to the same path and query under `base_url`.
+pub fn https_redirect_router(base_url: &str, status: &RedirectStatus) -> Router {
+    let status = match status {
+        RedirectStatus::MovedPermanently => StatusCode::MOVED_PERMANENTLY,
+        RedirectStatus::PermanentRedirect => StatusCode::PERMANENT_REDIRECT,
+    };
+    let base_url: Arc<str> = base_url.trim_end_matches('/').into();
 
-    debug!("Certificates loaded successfully");
-    Ok(cert_vec)
+    Router::new().fallback(move |uri: Uri| {
+        let location = redirect_location(&base_url, &uri);
+        async move { (status, [(LOCATION, location)]) }
+    })
 }
 
-fn read_next_cert(reader: &mut BufReader<File>) -> io::Result<Option<CertificateDer<'static>>> {
-    match certs(reader).next() {
-        Some(Ok(cert)) => {
-            debug!("Certificate processed successfully");
-            Ok(Some(cert))
-        }
-        Some(Err(_)) => {
-            error!("Invalid certificate encountered");
-            Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid cert"))
+fn redirect_location(base_url: &str, uri: &Uri) -> String {
+    let path_and_query = uri.path_and_query().map_or("/", PathAndQuery::as_str);
+    format!("{base_url}{path_and_query}")
+}
+
+/// Add a `Strict-Transport-Security` header to every response which does not already set one.
+pub fn with_hsts(router: Router, hsts: &HstsConfig) -> Router {
+    match HeaderValue::from_str(&hsts.header_value()) {
+        Ok(value) => router.layer(SetResponseHeaderLayer::if_not_present(
+            STRICT_TRANSPORT_SECURITY,
+            value,
+        )),
+        Err(e) => {
+            error!("Invalid HSTS header value, HSTS disabled: {:?}", e);
+            router
         }
-        None => Ok(None),
     }
 }
 
-fn load_keys(path: &Path) -> io::Result<PrivateKeyDer<'static>> {
-    debug!("Attempting to load keys from {:?}", path);
-    let file = open_key_file(path)?;
-    parse_keys_from_file(file)
+/// Accept connections on `listener` until `shutdown` is cancelled, then give open
+/// connections up to `grace_period` to finish.  Connections are served over TLS when
+/// `acceptor` is set.
+///
+/// Failed accepts are counted in the server stats.  Errors from a single pending
+/// connection are skipped, resource exhaustion such as running out of file descriptors
+/// backs off exponentially, and errors with the listening socket itself stop the
+/// listener.
+///
+/// While the server is at its connection limit the listener either stops accepting or
+/// closes each new connection at once, as configured by `website.connection_limits`.
+/// A paused listener which loses the last free slot to another holds the connection it
+/// accepted until a slot frees up.
+///
+/// # Errors
+///
+/// Returns an error if the listening socket becomes unusable.
+pub async fn serve<L: Listener>(
+    listener: L,
+    acceptor: Option<TlsAcceptor>,
+    settings: Arc<ConnectionSettings>,
+    router: Router,
+    shutdown: CancellationToken,
+    grace_period: Duration,
+) -> Result<(), Error> {
+    let addr = listener.local_addr()?;
+    let scheme = if acceptor.is_some() { "HTTPS" } else { "HTTP" };
+    debug!("Starting {} server at address {}", scheme, addr);
+
+    let mut connections = JoinSet::new();
+    let closing = shutdown.child_token();
+    let mut backoff = Backoff::default();
+    let mut error_log = ErrorLogLimiter::default();
+    let mut limit_log = ErrorLogLimiter::default();
+
+    let result = loop {
+        tokio::select! {
+            () = settings.limiter.wait_for_capacity() => {}
+            Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+            () = shutdown.cancelled() => break Ok(()),
+        }
+
+        let accepted = tokio::select! {
+            result = listener.accept() => result,
+            Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+            () = shutdown.cancelled() => break Ok(()),
+        };
+
+        let (stream, peer) = match accepted {
+            Ok(accepted) => {
+                backoff.reset();
+                accepted
+            }
+            Err(e) => {
+                settings.stats.record_accept_error();
+                let kind = AcceptErrorKind::of(&e);
+                if kind == AcceptErrorKind::Fatal {
+                    error!(
+                        "{} server at {} cannot accept connections: {}",
+                        scheme, addr, e
+                    );
+                    break Err(e.into());
+                }
+
+                let delay = (kind == AcceptErrorKind::Exhausted).then(|| backoff.next_delay());
+                if let Some(suppressed) = error_log.check(Instant::now()) {
+                    warn!(
+                        "Failed to accept a connection on {}: {} ({} similar errors suppressed, retrying in {:?})",
+                        addr,
+                        e,
+                        suppressed,
+                        delay.unwrap_or_default()
+                    );
+                }
+
+                if let Some(delay) = delay {
+                    tokio::select! {
+                        () = tokio::time::sleep(delay) => {}
+                        () = shutdown.cancelled() => break Ok(()),
+                    }
+                }
+                continue;
+            }
+        };
+
+        let Some(permit) = (tokio::select! {
+            permit = settings.limiter.acquire() => permit,
+            () = shutdown.cancelled() => break Ok(()),
+        }) else {
+            if let Some(suppressed) = limit_log.check(Instant::now()) {
+                warn!(
+                    "Connection limit reached, closing connection from {} on {} ({} similar suppressed)",
+                    peer, addr, suppressed
+                );
+            }
+            continue;
+        };
+
+        connections.spawn(serve_accepted(
+            stream,
+            peer,
+            permit,
+            acceptor.clone(),
+            settings.clone(),
+            router.clone(),
+            closing.clone(),
+        ));
+    };
+
+    debug!(
+        "{} server at {} stopped accepting connections",
+        scheme, addr
+    );
+    drop(listener);
+    closing.cancel();
+    drain_connections(connections, grace_period).await;
+
+    result
 }
 
-fn open_key_file(path: &Path) -> io::Result<File> {
-    if !path.exists() {
-        error!("Key file path does not exist: {:?}", path);
-        return Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"));
+/// Read any PROXY protocol header, check the client's connection limit and complete the
+/// TLS handshake of an accepted connection, then serve HTTP on it.  The connection is
+/// counted as open until `permit` is dropped on return.
+async fn serve_accepted<I>(
+    mut stream: I,
+    peer: PeerAddr,
+    mut permit: ConnectionPermit,
+    acceptor: Option<TlsAcceptor>,
+    settings: Arc<ConnectionSettings>,
+    router: Router,
+    closing: CancellationToken,
+) where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let Some((peer, mut extensions)) = settings.accept_proxied(&mut stream, peer).await else {
+        return;
+    };
+
+    if let PeerAddr::Tcp(addr) = &peer {
+        if !permit.try_assign_ip(addr.ip()) {
+            debug!(
+                "Per-IP connection limit reached, closing connection from {}",
+                peer
+            );
+            return;
+        }
     }
 
-    File::open(path).map_err(|e| {
-        error!("Failed to open key file: {:?}", e);
-        e
-    })
-}
+    let Some(acceptor) = acceptor else {
+        serve_connection(stream, peer, extensions, &settings, router, &closing).await;
+        return;
+    };
 
-fn parse_keys_from_file(file: File) -> io::Result<PrivateKeyDer<'static>> {
-    let mut reader = BufReader::new(file);
-    let keys: Result<Vec<_>, _> = pkcs8_private_keys(&mut reader).collect();
+    let handshake = acceptor.accept(stream);
+    let handshake = match settings.tls_handshake_timeout {
+        Some(timeout) => tokio::time::timeout(timeout, handshake).await,
+        None => Ok(handshake.await),
+    };
+    let Ok(handshake) = handshake else {
+        settings.stats.record_tls_handshake_timeout();
+        debug!("TLS handshake with {} timed out", peer);
+        return;
+    };
 
-    match keys {
-        Ok(keys) if keys.is_empty() => {
-            error!("No keys found in the file");
-            Err(io::Error::new(io::ErrorKind::NotFound, "no keys found"))
+    match handshake {
+        Ok(tls_stream) if tls_stream.get_ref().1.alpn_protocol() == Some(ACME_TLS_ALPN) => {
+            debug!(
+                "Completed ACME TLS-ALPN-01 validation handshake from {}",
+                peer
+            );
         }
-        Ok(mut keys) => {
-            debug!("Keys loaded successfully");
-            Ok(PrivateKeyDer::from(keys.remove(0)))
+        Ok(tls_stream) => {
+            if let Some(client_cert) = ClientCertificate::from_connection(tls_stream.get_ref().1) {
+                extensions.insert(client_cert);
+            }
+
+            serve_connection(tls_stream, peer, extensions, &settings, router, &closing).await;
         }
         Err(e) => {
-            error!("Error reading keys: {:?}", e);
-            Err(e)
+            error!("Failed to accept a TLS connection from {}: {:?}", peer, e);
         }
     }
 }
 
-pub async fn serve_https(
-    addr: SocketAddr,
+/// Serve HTTP on an accepted connection, adding `extensions` and the peer's
+/// `ConnectInfo<PeerAddr>` to every request, along with `ConnectInfo<SocketAddr>` for TCP
+/// peers.
+///
+/// The connection closes gracefully once `closing` is cancelled, it reaches its maximum
+/// lifetime or it goes idle.  A request counts as in progress until its response body
+/// has been sent.
+async fn serve_connection<I>(
+    io: I,
+    peer: PeerAddr,
+    mut extensions: Extensions,
+    settings: &ConnectionSettings,
     router: Router,
-    ssl_config: SslConfig,
-) -> Result<(), Error> {
-    let server_config = create_tls_config(&ssl_config)?;
-    let acceptor = TlsAcceptor::from(Arc::new(server_config));
-    let listener = TcpListener::bind(&addr).await?;
-
-    loop {
-        let (stream, _) = listener.accept().await?;
-        let acceptor = acceptor.clone();
-        let router_clone = router.clone();
-
-        tokio::spawn(async move {
-            match acceptor.accept(stream).await {
-                Ok(tls_stream) => {
-                    let io = TokioIo::new(tls_stream);
-
-                    let service = hyper::service::service_fn(move |req: Request<Incoming>| {
-                        let router = router_clone.clone();
-                        async move {
-                            match router.oneshot(req).await {
-                                Ok(response) => Ok::<_, hyper::Error>(response),
-                                Err(e) => {
-                                    error!("Encountered an error: {:?}", e);
-                                    Ok::<_, hyper::Error>(e.into_response())
-                                }
-                            }
-                        }
-                    });
-
-                    if let Err(err) = AutoBuilder::new(TokioExecutor::new())
-                        .serve_connection(io, service)
-                        .await
-                    {
-                        error!("Error serving TLS connection: {:?}", err);
-                    }
-                }
+    closing: &CancellationToken,
+) where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let activity = Arc::new(Activity::default());
+    let io = TokioIo::new(ActivityIo::new(io, activity.clone()));
+    if let PeerAddr::Tcp(addr) = peer {
+        extensions.insert(ConnectInfo(addr));
+    }
+    extensions.insert(ConnectInfo(peer.clone()));
+
+    let requests = activity.clone();
+    let service = hyper::service::service_fn(move |mut req: Request<Incoming>| {
+        let router = router.clone();
+        let request = requests.start_request();
+        req.extensions_mut().extend(extensions.clone());
+        async move {
+            let response = match router.oneshot(req).await {
+                Ok(response) => response,
                 Err(e) => {
-                    error!("Failed to accept a TLS connection: {:?}", e);
+                    error!("Encountered an error: {:?}", e);
+                    e.into_response()
                 }
-            }
-        });
-    }
-}
+            };
+            Ok::<_, hyper::Error>(response.map(|body| Body::new(TrackedBody::new(body, request))))
+        }
+    });
 
-#[allow(clippy::module_name_repetitions)]
-pub async fn serve_http(addr: SocketAddr, router: Router) -> Result<(), Error> {
-    debug!("Starting HTTP server at address {}", addr);
-
-    let listener = TcpListener::bind(addr).await?;
-
-    loop {
-        let router_clone = router.clone();
-
-        match listener.accept().await {
-            Ok((stream, _)) => {
-                let io = TokioIo::new(stream);
-
-                tokio::task::spawn(async move {
-                    let service = hyper::service::service_fn(move |req: Request<Incoming>| {
-                        let router = router_clone.clone();
-                        async move {
-                            match router.oneshot(req).await {
-                                Ok(response) => Ok::<_, hyper::Error>(response),
-                                Err(e) => {
-                                    error!("Encountered an error: {:?}", e);
-                                    Ok::<_, hyper::Error>(e.into_response())
-                                }
-                            }
-                        }
-                    });
-
-                    if let Err(err) = AutoBuilder::new(TokioExecutor::new())
-                        .serve_connection(io, service)
-                        .await
-                    {
-                        error!("Error serving connection: {:?}", err);
-                    }
-                });
+    let conn = settings.builder.serve_connection(io, service);
+    tokio::pin!(conn);
+
+    let lifetime = async {
+        match settings.max_lifetime {
+            Some(max_lifetime) => tokio::time::sleep(max_lifetime).await,
+            None => future::pending().await,
+        }
+    };
+    tokio::pin!(lifetime);
+
+    let idle = async {
+        match settings.idle_timeout {
+            Some(idle_timeout) => activity.idle(idle_timeout).await,
+            None => future::pending().await,
+        }
+    };
+    tokio::pin!(idle);
+
+    let mut shutting_down = false;
+    let result = loop {
+        tokio::select! {
+            result = conn.as_mut() => break result,
+            () = closing.cancelled(), if !shutting_down => {
+                shutting_down = true;
+                conn.as_mut().graceful_shutdown();
             }
-            Err(e) => {
-                error!("Failed to accept connection: {:?}", e);
+            () = &mut lifetime, if !shutting_down => {
+                settings.stats.record_lifetime_expiration();
+                debug!("Connection from {} reached its maximum lifetime", peer);
+                shutting_down = true;
+                conn.as_mut().graceful_shutdown();
+            }
+            () = &mut idle, if !shutting_down => {
+                settings.stats.record_idle_timeout();
+                debug!("Closing idle connection from {}", peer);
+                shutting_down = true;
+                conn.as_mut().graceful_shutdown();
             }
         }
+    };
+
+    match result {
+        Ok(()) => {}
+        Err(err)
+            if err
+                .downcast_ref::<hyper::Error>()
+                .is_some_and(hyper::Error::is_timeout) =>
+        {
+            settings.stats.record_header_read_timeout();
+            debug!("Timed out reading request headers from {}", peer);
+        }
+        Err(err) => error!("Error serving connection from {}: {:?}", peer, err),
+    }
+}
+
+/// Wait up to `grace_period` for `connections` to finish, then abort the rest.
+async fn drain_connections(mut connections: JoinSet<()>, grace_period: Duration) {
+    debug!("Draining {} open connections", connections.len());
+
+    let drained = async { while connections.join_next().await.is_some() {} };
+    if tokio::time::timeout(grace_period, drained).await.is_err() {
+        warn!(
+            "Grace period of {:?} elapsed, closing {} remaining connections",
+            grace_period,
+            connections.len()
+        );
+        connections.abort_all();
+    }
+
+    while connections.join_next().await.is_some() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirect_location_keeps_path_and_query() {
+        let uri: Uri = "/api/items?page=2".parse().unwrap();
+        assert_eq!(
+            redirect_location("https://example.com:8443", &uri),
+            "https://example.com:8443/api/items?page=2"
+        );
+    }
+
+    #[test]
+    fn redirect_location_defaults_to_root() {
+        let uri: Uri = "http://example.com".parse().unwrap();
+        assert_eq!(
+            redirect_location("https://example.com", &uri),
+            "https://example.com/"
+        );
     }
 }
@@ -0,0 +1,320 @@
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use {
+    askama_axum::IntoResponse,
+    bytes::{Buf, Bytes},
+    h3::server::{Connection as H3Connection, RequestResolver, RequestStream},
+    http_body_util::BodyExt,
+    hyper::body::{Body as HttpBody, Frame},
+    quinn::{crypto::rustls::QuicServerConfig, Endpoint, EndpointConfig, Incoming, TokioRuntime},
+    rustls_pki_types::CertificateDer,
+    socket2::{Domain, Protocol, Socket, Type},
+    tokio::task::JoinSet,
+    tokio_rustls::rustls::ServerConfig,
+    tokio_util::sync::CancellationToken,
+    tower::ServiceExt,
+    tower_http::set_header::SetResponseHeaderLayer,
+};
+
+use crate::{
+    axum::{
+        body::Body,
+        extract::{ConnectInfo, Request},
+        http::{header::ALT_SVC, Extensions, HeaderValue, Response},
+        BoxError, Router,
+    },
+    tracing::{debug, error, warn},
+    util::{client_cert::ClientCertificate, listener::PeerAddr},
+    Error,
+};
+
+/// The ALPN protocol identifier of HTTP/3.
+const H3_ALPN: &[u8] = b"h3";
+
+/// How long, in seconds, clients may remember the `Alt-Svc` advertisement.
+const ALT_SVC_MAX_AGE_SECS: u32 = 86_400;
+
+/// Advertise HTTP/3 on UDP `port` with an `Alt-Svc` header on every response which does
+/// not already set one.
+pub fn with_alt_svc(router: Router, port: u16) -> Router {
+    let value = format!("h3=\":{port}\"; ma={ALT_SVC_MAX_AGE_SECS}");
+    match HeaderValue::from_str(&value) {
+        Ok(value) => router.layer(SetResponseHeaderLayer::if_not_present(ALT_SVC, value)),
+        Err(e) => {
+            error!(
+                "Invalid Alt-Svc header value, HTTP/3 not advertised: {:?}",
+                e
+            );
+            router
+        }
+    }
+}
+
+/// Bind a QUIC endpoint on UDP `addr` which negotiates HTTP/3 using the certificates,
+/// client verification and protocol settings of `tls_config`.
+///
+/// # Errors
+///
+/// Returns an error if the TLS settings cannot be used for QUIC, which requires TLS 1.3,
+/// or the socket cannot be bound.
+pub fn bind_endpoint(
+    addr: SocketAddr,
+    ipv6_only: bool,
+    tls_config: &ServerConfig,
+) -> Result<Endpoint, Error> {
+    let mut tls_config = tls_config.clone();
+    tls_config.alpn_protocols = vec![H3_ALPN.to_vec()];
+
+    let crypto = QuicServerConfig::try_from(tls_config).map_err(|e| {
+        Error::InvalidSslConfig(format!("the TLS settings cannot be used for HTTP/3: {e}"))
+    })?;
+    let server_config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+
+    bind_udp(addr, ipv6_only)
+        .and_then(|socket| {
+            Endpoint::new(
+                EndpointConfig::default(),
+                Some(server_config),
+                socket,
+                Arc::new(TokioRuntime),
+            )
+        })
+        .map_err(|source| {
+            error!("Failed to bind HTTP/3 endpoint {}: {}", addr, source);
+            Error::BindError { addr, source }
+        })
+}
+
+fn bind_udp(addr: SocketAddr, ipv6_only: bool) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+
+    if addr.is_ipv6() {
+        socket.set_only_v6(ipv6_only)?;
+    }
+
+    socket.bind(&addr.into())?;
+    Ok(socket.into())
+}
+
+/// Serve HTTP/3 on `endpoint` until `shutdown` is cancelled, then ask clients to finish
+/// their requests and close the endpoint after at most `grace_period`.
+pub async fn serve_http3(
+    endpoint: Endpoint,
+    router: Router,
+    shutdown: CancellationToken,
+    grace_period: Duration,
+) -> Result<(), Error> {
+    let addr = endpoint.local_addr()?;
+    debug!("Starting HTTP/3 server at address {}", addr);
+
+    let mut connections = JoinSet::new();
+
+    loop {
+        let incoming = tokio::select! {
+            incoming = endpoint.accept() => incoming,
+            Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+            () = shutdown.cancelled() => break,
+        };
+        let Some(incoming) = incoming else {
+            break;
+        };
+
+        connections.spawn(serve_quic_connection(
+            incoming,
+            router.clone(),
+            shutdown.clone(),
+        ));
+    }
+
+    debug!("HTTP/3 server at {} stopped accepting connections", addr);
+    endpoint.set_server_config(None);
+
+    let drained = async { while connections.join_next().await.is_some() {} };
+    if tokio::time::timeout(grace_period, drained).await.is_err() {
+        warn!(
+            "Grace period of {:?} elapsed, closing {} remaining HTTP/3 connections",
+            grace_period,
+            connections.len()
+        );
+        connections.abort_all();
+        while connections.join_next().await.is_some() {}
+    }
+
+    endpoint.close(0u32.into(), b"server shutting down");
+    endpoint.wait_idle().await;
+
+    Ok(())
+}
+
+enum ConnectionEvent<T> {
+    Accepted(T),
+    Shutdown,
+    RequestDone,
+}
+
+async fn serve_quic_connection(incoming: Incoming, router: Router, shutdown: CancellationToken) {
+    let peer = incoming.remote_address();
+    let connection = match incoming.await {
+        Ok(connection) => connection,
+        Err(e) => {
+            error!("Failed to accept a QUIC connection from {}: {}", peer, e);
+            return;
+        }
+    };
+
+    let mut extensions = Extensions::new();
+    extensions.insert(ConnectInfo(peer));
+    extensions.insert(ConnectInfo(PeerAddr::Tcp(peer)));
+    if let Some(client_cert) = connection
+        .peer_identity()
+        .and_then(|identity| identity.downcast::<Vec<CertificateDer<'static>>>().ok())
+        .and_then(|chain| ClientCertificate::from_chain(&chain))
+    {
+        extensions.insert(client_cert);
+    }
+
+    let mut connection: H3Connection<_, Bytes> = match h3::server::builder()
+        .build(h3_quinn::Connection::new(connection))
+        .await
+    {
+        Ok(connection) => connection,
+        Err(e) => {
+            error!("Failed to establish HTTP/3 with {}: {}", peer, e);
+            return;
+        }
+    };
+
+    let mut requests = JoinSet::new();
+    let mut shutting_down = false;
+
+    loop {
+        let event = tokio::select! {
+            accepted = connection.accept() => ConnectionEvent::Accepted(accepted),
+            () = shutdown.cancelled(), if !shutting_down => ConnectionEvent::Shutdown,
+            Some(_) = requests.join_next(), if !requests.is_empty() => ConnectionEvent::RequestDone,
+        };
+
+        match event {
+            ConnectionEvent::Accepted(Ok(Some(resolver))) => {
+                requests.spawn(serve_request(resolver, router.clone(), extensions.clone()));
+            }
+            ConnectionEvent::Accepted(Ok(None)) => break,
+            ConnectionEvent::Accepted(Err(e)) => {
+                if !e.is_h3_no_error() {
+                    error!("Error serving HTTP/3 connection from {}: {}", peer, e);
+                }
+                break;
+            }
+            ConnectionEvent::Shutdown => {
+                shutting_down = true;
+                // Let every request accepted so far complete, and refuse any later ones.
+                if let Err(e) = connection.shutdown(1).await {
+                    debug!("Failed to send GOAWAY to {}: {}", peer, e);
+                    break;
+                }
+            }
+            ConnectionEvent::RequestDone => {}
+        }
+    }
+
+    while requests.join_next().await.is_some() {}
+}
+
+async fn serve_request(
+    resolver: RequestResolver<h3_quinn::Connection, Bytes>,
+    router: Router,
+    extensions: Extensions,
+) {
+    let (request, stream) = match resolver.resolve_request().await {
+        Ok(request) => request,
+        Err(e) => {
+            debug!("Failed to read HTTP/3 request: {}", e);
+            return;
+        }
+    };
+
+    let (mut send, recv) = stream.split();
+    let (mut parts, ()) = request.into_parts();
+    parts.extensions.extend(extensions);
+    let request = Request::from_parts(
+        parts,
+        Body::new(RequestBody {
+            stream: recv,
+            data_done: false,
+        }),
+    );
+
+    let response = match router.oneshot(request).await {
+        Ok(response) => response,
+        Err(e) => {
+            error!("Encountered an error: {:?}", e);
+            e.into_response()
+        }
+    };
+
+    if let Err(e) = send_response(&mut send, response).await {
+        debug!("Failed to send HTTP/3 response: {}", e);
+    }
+}
+
+async fn send_response(
+    send: &mut RequestStream<h3_quinn::SendStream<Bytes>, Bytes>,
+    response: Response<Body>,
+) -> Result<(), BoxError> {
+    let (parts, mut body) = response.into_parts();
+    send.send_response(Response::from_parts(parts, ())).await?;
+
+    while let Some(frame) = body.frame().await {
+        match frame?.into_data() {
+            Ok(data) => send.send_data(data).await?,
+            Err(frame) => {
+                if let Ok(trailers) = frame.into_trailers() {
+                    send.send_trailers(trailers).await?;
+                }
+            }
+        }
+    }
+
+    send.finish().await?;
+    Ok(())
+}
+
+/// A request body read from its QUIC stream as the handler consumes it.
+struct RequestBody {
+    stream: RequestStream<h3_quinn::RecvStream, Bytes>,
+    data_done: bool,
+}
+
+impl HttpBody for RequestBody {
+    type Data = Bytes;
+    type Error = h3::error::StreamError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if !self.data_done {
+            match ready!(self.stream.poll_recv_data(cx))? {
+                Some(mut data) => {
+                    return Poll::Ready(Some(Ok(Frame::data(
+                        data.copy_to_bytes(data.remaining()),
+                    ))));
+                }
+                None => self.data_done = true,
+            }
+        }
+
+        self.stream.poll_recv_trailers(cx).map(|trailers| {
+            trailers
+                .transpose()
+                .map(|trailers| trailers.map(Frame::trailers))
+        })
+    }
+}
@@ -0,0 +1,165 @@
+use std::{net::IpAddr, sync::Arc};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::util::{
+    config::{ConnectionLimitAction, ConnectionLimitConfig},
+    stats::ServerStats,
+};
+
+/// Enforces the server's connection limits across every listener, keeping the open
+/// connection gauges in [`ServerStats`] up to date.
+#[derive(Debug)]
+pub struct ConnectionLimiter {
+    config: ConnectionLimitConfig,
+    stats: Arc<ServerStats>,
+    slots: Option<Arc<Semaphore>>,
+}
+
+impl ConnectionLimiter {
+    #[must_use]
+    pub fn new(config: ConnectionLimitConfig, stats: Arc<ServerStats>) -> Self {
+        let slots = config
+            .max_connections
+            .map(|max| Arc::new(Semaphore::new(max.min(Semaphore::MAX_PERMITS))));
+        Self {
+            config,
+            stats,
+            slots,
+        }
+    }
+
+    fn pauses(&self) -> bool {
+        self.config.on_limit == ConnectionLimitAction::Pause
+    }
+
+    /// Wait until a connection may be accepted.  Returns at once unless listeners pause
+    /// while the server is at its connection limit.  No slot is reserved, so that accept
+    /// loops waiting for a connection do not use up the limit.
+    pub async fn wait_for_capacity(&self) {
+        if let Some(slots) = self.slots.as_ref().filter(|_| self.pauses()) {
+            drop(slots.acquire().await);
+        }
+    }
+
+    /// Count a newly accepted connection as open.  While listeners pause at the
+    /// connection limit this waits for a free slot, otherwise it returns `None` when the
+    /// connection should be closed because the server is at its limit.
+    pub async fn acquire(self: &Arc<Self>) -> Option<ConnectionPermit> {
+        let slot = match &self.slots {
+            Some(slots) if self.pauses() => Some(Arc::clone(slots).acquire_owned().await.ok()?),
+            Some(slots) => {
+                let Ok(slot) = Arc::clone(slots).try_acquire_owned() else {
+                    self.stats.record_connection_limit_rejection();
+                    return None;
+                };
+                Some(slot)
+            }
+            None => None,
+        };
+
+        self.stats.open_connection();
+        Some(ConnectionPermit {
+            limiter: Arc::clone(self),
+            ip: None,
+            _slot: slot,
+        })
+    }
+}
+
+/// Keeps a connection counted as open until dropped.
+#[derive(Debug)]
+pub struct ConnectionPermit {
+    limiter: Arc<ConnectionLimiter>,
+    ip: Option<IpAddr>,
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+impl ConnectionPermit {
+    /// Count the connection against its client's IP address, returning `false` when it
+    /// should be closed because that client is at its connection limit.
+    pub fn try_assign_ip(&mut self, ip: IpAddr) -> bool {
+        let limiter = &self.limiter;
+        if limiter
+            .stats
+            .try_open_connection_from(ip, limiter.config.max_connections_per_ip)
+        {
+            self.ip = Some(ip);
+            true
+        } else {
+            limiter.stats.record_connection_limit_rejection();
+            false
+        }
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        if let Some(ip) = self.ip {
+            self.limiter.stats.close_connection_from(ip);
+        }
+        self.limiter.stats.close_connection();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn limiter(config: ConnectionLimitConfig) -> Arc<ConnectionLimiter> {
+        Arc::new(ConnectionLimiter::new(
+            config,
+            Arc::new(ServerStats::default()),
+        ))
+    }
+
+    #[tokio::test]
+    async fn permits_track_global_and_per_ip_limits() {
+        let limiter = limiter(ConnectionLimitConfig {
+            max_connections: Some(2),
+            max_connections_per_ip: Some(1),
+            on_limit: ConnectionLimitAction::Close,
+        });
+        let stats = &limiter.stats;
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+
+        let mut first = limiter.acquire().await.unwrap();
+        assert!(first.try_assign_ip(ip));
+        let mut second = limiter.acquire().await.unwrap();
+        assert!(!second.try_assign_ip(ip));
+        assert!(limiter.acquire().await.is_none());
+        assert_eq!(stats.open_connections(), 2);
+        assert_eq!(stats.open_connections_from(ip), 1);
+        assert_eq!(stats.connection_limit_rejections(), 2);
+
+        drop(first);
+        drop(second);
+        assert_eq!(stats.open_connections(), 0);
+        assert_eq!(stats.open_connections_from(ip), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pausing_waits_for_a_free_slot_without_reserving_it() {
+        let limiter = limiter(ConnectionLimitConfig {
+            max_connections: Some(1),
+            ..Default::default()
+        });
+        let permit = limiter.acquire().await.unwrap();
+
+        let waiting = tokio::time::timeout(Duration::from_secs(1), limiter.wait_for_capacity());
+        assert!(waiting.await.is_err(), "no slot is free");
+        let waiting = tokio::time::timeout(Duration::from_secs(1), limiter.acquire());
+        assert!(
+            waiting.await.is_err(),
+            "accepted connections wait for a slot"
+        );
+
+        drop(permit);
+        limiter.wait_for_capacity().await;
+        limiter.wait_for_capacity().await;
+        assert!(limiter.acquire().await.is_some());
+        assert_eq!(limiter.stats.connection_limit_rejections(), 0);
+    }
+}
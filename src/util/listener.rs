@@ -0,0 +1,609 @@
+use std::{
+    env,
+    fmt::{self, Display, Formatter},
+    future::Future,
+    io,
+    net::SocketAddr,
+    path::PathBuf,
+    time::Duration,
+};
+
+use {
+    listenfd::ListenFd,
+    socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type},
+    tokio::{
+        io::{AsyncRead, AsyncWrite},
+        net::{TcpListener, TcpStream},
+    },
+};
+
+use crate::{
+    tracing::{debug, error, info},
+    util::config::{InheritFd, ListenerConfig, ListenerKind, TcpSocketConfig},
+    Error,
+};
+
+/// The address of the peer on the other end of a connection.
+///
+/// Inserted into every request as `ConnectInfo<PeerAddr>`, so handlers can extract it
+/// with `axum::extract::ConnectInfo<PeerAddr>`.  Requests from TCP peers also carry
+/// `ConnectInfo<SocketAddr>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerAddr {
+    Tcp(SocketAddr),
+    Unix(UnixPeer),
+}
+
+impl Display for PeerAddr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            Self::Unix(peer) => write!(f, "{peer}"),
+        }
+    }
+}
+
+/// The credentials of a process connected over a unix domain socket, where the platform
+/// reports them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnixPeer {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub pid: Option<i32>,
+}
+
+impl Display for UnixPeer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "unix")?;
+        if let Some(pid) = self.pid {
+            write!(f, " pid={pid}")?;
+        }
+        if let Some(uid) = self.uid {
+            write!(f, " uid={uid}")?;
+        }
+        if let Some(gid) = self.gid {
+            write!(f, " gid={gid}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The local address a listener is bound to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl ListenAddr {
+    #[must_use]
+    pub const fn socket_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Tcp(addr) => Some(*addr),
+            Self::Unix(_) => None,
+        }
+    }
+}
+
+impl Display for ListenAddr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// A bound socket which yields connections for the accept loops in [`super::http`].
+pub trait Listener: Send + 'static {
+    type Io: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    fn accept(&self) -> impl Future<Output = io::Result<(Self::Io, PeerAddr)>> + Send;
+
+    /// # Errors
+    ///
+    /// Returns an error if the operating system cannot report the local address.
+    fn local_addr(&self) -> io::Result<ListenAddr>;
+}
+
+impl Listener for TcpListener {
+    type Io = TcpStream;
+
+    async fn accept(&self) -> io::Result<(Self::Io, PeerAddr)> {
+        let (stream, addr) = Self::accept(self).await?;
+        Ok((stream, PeerAddr::Tcp(addr)))
+    }
+
+    fn local_addr(&self) -> io::Result<ListenAddr> {
+        Self::local_addr(self).map(ListenAddr::Tcp)
+    }
+}
+
+/// A TCP listener which applies the listener's `tcp` options to every accepted
+/// connection.
+pub struct TcpSocketListener {
+    inner: TcpListener,
+    nodelay: bool,
+    keepalive: Option<TcpKeepalive>,
+}
+
+impl TcpSocketListener {
+    #[must_use]
+    pub fn new(inner: TcpListener, config: &TcpSocketConfig) -> Self {
+        Self {
+            inner,
+            nodelay: config.nodelay,
+            keepalive: config
+                .keepalive_secs
+                .map(|secs| TcpKeepalive::new().with_time(Duration::from_secs(secs))),
+        }
+    }
+
+    fn configure(&self, stream: &TcpStream) -> io::Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+        if let Some(keepalive) = &self.keepalive {
+            SockRef::from(stream).set_tcp_keepalive(keepalive)?;
+        }
+        Ok(())
+    }
+}
+
+impl Listener for TcpSocketListener {
+    type Io = TcpStream;
+
+    async fn accept(&self) -> io::Result<(Self::Io, PeerAddr)> {
+        let (stream, addr) = self.inner.accept().await?;
+        if let Err(e) = self.configure(&stream) {
+            debug!("Failed to set socket options for {}: {}", addr, e);
+        }
+        Ok((stream, PeerAddr::Tcp(addr)))
+    }
+
+    fn local_addr(&self) -> io::Result<ListenAddr> {
+        self.inner.local_addr().map(ListenAddr::Tcp)
+    }
+}
+
+/// Any listener the server can bind from config.
+pub enum BoundSocket {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(unix::UnixSocketListener),
+}
+
+impl BoundSocket {
+    /// # Errors
+    ///
+    /// Returns an error if the operating system cannot report the local address.
+    pub fn local_addr(&self) -> io::Result<ListenAddr> {
+        match self {
+            Self::Tcp(listener) => Listener::local_addr(listener),
+            #[cfg(unix)]
+            Self::Unix(listener) => listener.local_addr(),
+        }
+    }
+}
+
+/// Sockets passed to the process by a service manager, following the systemd socket
+/// activation protocol (`LISTEN_FDS`, `LISTEN_PID` and `LISTEN_FDNAMES`).
+pub struct InheritedSockets {
+    fds: ListenFd,
+    names: Vec<String>,
+}
+
+impl InheritedSockets {
+    /// Claim the sockets described by the environment.  The activation variables are
+    /// removed, so this should only be called once per process.
+    #[must_use]
+    pub fn from_env() -> Self {
+        let names = env::var("LISTEN_FDNAMES")
+            .map(|names| parse_fd_names(&names))
+            .unwrap_or_default();
+        let fds = ListenFd::from_env();
+
+        if fds.len() > 0 {
+            info!(
+                "Socket activation provided {} file descriptors {:?}",
+                fds.len(),
+                names
+            );
+        }
+
+        Self { fds, names }
+    }
+
+    #[must_use]
+    pub fn empty() -> Self {
+        Self {
+            fds: ListenFd::empty(),
+            names: Vec::new(),
+        }
+    }
+
+    fn index_of(&self, inherit_fd: &InheritFd) -> Option<usize> {
+        let index = match inherit_fd {
+            InheritFd::Index(index) => Some(*index),
+            InheritFd::Name(name) => self.names.iter().position(|n| n == name),
+        };
+        index.filter(|index| *index < self.fds.len())
+    }
+
+    fn take(
+        &mut self,
+        listener_config: &ListenerConfig,
+        inherit_fd: &InheritFd,
+    ) -> io::Result<Option<BoundSocket>> {
+        let Some(index) = self.index_of(inherit_fd) else {
+            return Ok(None);
+        };
+
+        match listener_config.kind {
+            ListenerKind::Tcp => match self.fds.take_tcp_listener(index)? {
+                Some(listener) => {
+                    listener.set_nonblocking(true)?;
+                    Ok(Some(BoundSocket::Tcp(TcpListener::from_std(listener)?)))
+                }
+                None => Ok(None),
+            },
+            #[cfg(unix)]
+            ListenerKind::Unix => match self.fds.take_unix_listener(index)? {
+                Some(listener) => Ok(Some(BoundSocket::Unix(
+                    unix::UnixSocketListener::from_inherited(listener)?,
+                ))),
+                None => Ok(None),
+            },
+            #[cfg(not(unix))]
+            ListenerKind::Unix => Ok(None),
+        }
+    }
+}
+
+fn parse_fd_names(names: &str) -> Vec<String> {
+    names.split(':').map(str::to_owned).collect()
+}
+
+/// Bind the socket described by a listener definition, preferring an inherited socket
+/// when the definition asks for one and the process was socket activated.
+///
+/// # Errors
+///
+/// Returns [`Error::BindError`], [`Error::UnixBindError`] or [`Error::InheritFdError`] if
+/// the socket cannot be bound or adopted, and [`Error::InvalidListenerConfig`] if a TCP
+/// listener expects PROXY protocol headers without any trusted networks.
+pub fn bind(
+    listener_config: &ListenerConfig,
+    inherited: &mut InheritedSockets,
+) -> Result<BoundSocket, Error> {
+    if listener_config.kind == ListenerKind::Tcp
+        && listener_config.proxy_protocol
+        && listener_config.proxy_protocol_trusted.is_empty()
+    {
+        return Err(Error::InvalidListenerConfig {
+            listener: listener_config.display_name(),
+            cause: "proxy_protocol requires at least one network in proxy_protocol_trusted".into(),
+        });
+    }
+
+    if let Some(inherit_fd) = &listener_config.inherit_fd {
+        match inherited.take(listener_config, inherit_fd) {
+            Ok(Some(socket)) => {
+                info!(
+                    "Listener {} adopted inherited socket {:?}",
+                    listener_config.display_name(),
+                    inherit_fd
+                );
+                return Ok(socket);
+            }
+            Ok(None) => debug!(
+                "No inherited socket {:?} for listener {}, binding normally",
+                inherit_fd,
+                listener_config.display_name()
+            ),
+            Err(source) => {
+                error!(
+                    "Failed to adopt inherited socket for listener {}: {}",
+                    listener_config.display_name(),
+                    source
+                );
+                return Err(Error::InheritFdError {
+                    listener: listener_config.display_name(),
+                    source,
+                });
+            }
+        }
+    }
+
+    match listener_config.kind {
+        ListenerKind::Tcp => bind_tcp(listener_config).map(BoundSocket::Tcp),
+        ListenerKind::Unix => bind_unix(listener_config),
+    }
+}
+
+/// Bind the extra sockets of a TCP listener with `reuse_port` set, on the address its
+/// first socket was bound to.  Returns no sockets for any other listener.
+///
+/// Must be called from within a tokio runtime, whose worker count is the default number
+/// of acceptors.
+///
+/// # Errors
+///
+/// Returns [`Error::BindError`] if a socket cannot be bound.
+pub fn bind_acceptors(
+    listener_config: &ListenerConfig,
+    socket: &BoundSocket,
+) -> Result<Vec<TcpListener>, Error> {
+    let BoundSocket::Tcp(first) = socket else {
+        return Ok(Vec::new());
+    };
+    if !listener_config.reuse_port || listener_config.inherit_fd.is_some() {
+        return Ok(Vec::new());
+    }
+
+    let count = listener_config
+        .acceptors
+        .unwrap_or_else(|| tokio::runtime::Handle::current().metrics().num_workers());
+    let addr = first.local_addr().map_err(|source| Error::BindError {
+        addr: listener_config.socket_addr(),
+        source,
+    })?;
+
+    (1..count)
+        .map(|_| {
+            bind_socket(addr, listener_config)
+                .map_err(|source| bind_error(listener_config, addr, source))
+        })
+        .collect()
+}
+
+fn bind_tcp(listener_config: &ListenerConfig) -> Result<TcpListener, Error> {
+    let addr = listener_config.socket_addr();
+    bind_socket(addr, listener_config).map_err(|source| bind_error(listener_config, addr, source))
+}
+
+fn bind_error(listener_config: &ListenerConfig, addr: SocketAddr, source: io::Error) -> Error {
+    error!(
+        "Failed to bind listener {}: {}",
+        listener_config.display_name(),
+        source
+    );
+    Error::BindError { addr, source }
+}
+
+fn bind_socket(addr: SocketAddr, listener_config: &ListenerConfig) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+
+    if addr.is_ipv6() {
+        socket.set_only_v6(listener_config.ipv6_only)?;
+    }
+
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+
+    if listener_config.reuse_port {
+        set_reuse_port(&socket)?;
+    }
+
+    let tcp = &listener_config.tcp;
+    if let Some(size) = tcp.send_buffer_size {
+        socket.set_send_buffer_size(size)?;
+    }
+    if let Some(size) = tcp.recv_buffer_size {
+        socket.set_recv_buffer_size(size)?;
+    }
+
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(i32::try_from(tcp.backlog).unwrap_or(i32::MAX))?;
+
+    TcpListener::from_std(socket.into())
+}
+
+#[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+fn set_reuse_port(socket: &Socket) -> io::Result<()> {
+    socket.set_reuse_port(true)
+}
+
+#[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos")))))]
+fn set_reuse_port(_socket: &Socket) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "SO_REUSEPORT is not supported on this platform",
+    ))
+}
+
+#[cfg(unix)]
+fn bind_unix(listener_config: &ListenerConfig) -> Result<BoundSocket, Error> {
+    let Some(path) = listener_config.path.clone() else {
+        return Err(Error::InvalidListenerConfig {
+            listener: listener_config.display_name(),
+            cause: "unix listeners require a path".into(),
+        });
+    };
+
+    unix::bind(path.clone(), listener_config)
+        .map(BoundSocket::Unix)
+        .map_err(|source| {
+            error!(
+                "Failed to bind unix listener {}: {}",
+                listener_config.display_name(),
+                source
+            );
+            Error::UnixBindError { path, source }
+        })
+}
+
+#[cfg(not(unix))]
+fn bind_unix(listener_config: &ListenerConfig) -> Result<BoundSocket, Error> {
+    Err(Error::InvalidListenerConfig {
+        listener: listener_config.display_name(),
+        cause: "unix listeners are not supported on this platform".into(),
+    })
+}
+
+#[cfg(unix)]
+pub mod unix {
+    use std::{
+        fs::{self, Permissions},
+        io,
+        os::unix::fs::{chown, FileTypeExt, PermissionsExt},
+        path::{Path, PathBuf},
+    };
+
+    use tokio::net::{UnixListener, UnixStream};
+
+    use crate::{
+        tracing::{debug, warn},
+        util::config::ListenerConfig,
+    };
+
+    use super::{ListenAddr, Listener, PeerAddr, UnixPeer};
+
+    /// A unix domain socket listener which removes the socket file it created when dropped.
+    pub struct UnixSocketListener {
+        inner: UnixListener,
+        path: PathBuf,
+        remove_on_drop: bool,
+    }
+
+    impl UnixSocketListener {
+        /// Adopt a socket created by a service manager, which keeps ownership of its file.
+        pub(super) fn from_inherited(
+            listener: std::os::unix::net::UnixListener,
+        ) -> io::Result<Self> {
+            let path = listener
+                .local_addr()?
+                .as_pathname()
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+            listener.set_nonblocking(true)?;
+
+            Ok(Self {
+                inner: UnixListener::from_std(listener)?,
+                path,
+                remove_on_drop: false,
+            })
+        }
+    }
+
+    impl Listener for UnixSocketListener {
+        type Io = UnixStream;
+
+        async fn accept(&self) -> io::Result<(Self::Io, PeerAddr)> {
+            let (stream, _) = self.inner.accept().await?;
+            let peer = stream
+                .peer_cred()
+                .map(|cred| UnixPeer {
+                    uid: Some(cred.uid()),
+                    gid: Some(cred.gid()),
+                    pid: cred.pid(),
+                })
+                .unwrap_or_default();
+            Ok((stream, PeerAddr::Unix(peer)))
+        }
+
+        fn local_addr(&self) -> io::Result<ListenAddr> {
+            Ok(ListenAddr::Unix(self.path.clone()))
+        }
+    }
+
+    impl Drop for UnixSocketListener {
+        fn drop(&mut self) {
+            if !self.remove_on_drop {
+                return;
+            }
+
+            if let Err(e) = fs::remove_file(&self.path) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!("Failed to remove unix socket {:?}: {}", self.path, e);
+                }
+            }
+        }
+    }
+
+    pub(super) fn bind(
+        path: PathBuf,
+        listener_config: &ListenerConfig,
+    ) -> io::Result<UnixSocketListener> {
+        if listener_config.remove_stale_socket {
+            remove_stale_socket(&path)?;
+        }
+
+        let inner = UnixListener::bind(&path)?;
+        // From here on the listener owns the socket file and removes it if setup fails.
+        let listener = UnixSocketListener {
+            inner,
+            path,
+            remove_on_drop: true,
+        };
+
+        if let Some(mode) = listener_config.socket_mode {
+            fs::set_permissions(&listener.path, Permissions::from_mode(mode))?;
+        }
+
+        if listener_config.socket_owner.is_some() || listener_config.socket_group.is_some() {
+            chown(
+                &listener.path,
+                listener_config.socket_owner,
+                listener_config.socket_group,
+            )?;
+        }
+
+        Ok(listener)
+    }
+
+    /// Remove a socket file left behind by a previous process, refusing to touch anything
+    /// which is not a socket or which still has a live listener.
+    fn remove_stale_socket(path: &Path) -> io::Result<()> {
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        if !metadata.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "path exists and is not a socket",
+            ));
+        }
+
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "another process is listening on this socket",
+            ));
+        }
+
+        debug!("Removing stale unix socket {:?}", path);
+        fs::remove_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_fd_names_splits_on_colons() {
+        assert_eq!(parse_fd_names("http:https"), vec!["http", "https"]);
+        assert_eq!(parse_fd_names("web"), vec!["web"]);
+    }
+
+    #[test]
+    fn empty_inherited_sockets_resolve_nothing() {
+        let inherited = InheritedSockets::empty();
+        assert_eq!(inherited.index_of(&InheritFd::Index(0)), None);
+        assert_eq!(inherited.index_of(&InheritFd::Name("http".into())), None);
+    }
+
+    #[test]
+    fn proxy_protocol_requires_trusted_networks() {
+        let config = ListenerConfig {
+            port: 0,
+            proxy_protocol: true,
+            ..ListenerConfig::default()
+        };
+        let result = bind(&config, &mut InheritedSockets::empty());
+        assert!(matches!(result, Err(Error::InvalidListenerConfig { .. })));
+    }
+}
@@ -1,9 +1,9 @@
 use {tracing_appender::non_blocking::WorkerGuard, tracing_subscriber::fmt::format::FmtSpan};
 
 use crate::{
-    Verbosity,
     tracing::{debug, error, info, subscriber::set_global_default, trace, warn, Level},
     util::config::Config,
+    Verbosity,
 };
 
 pub struct Logger {
@@ -1,4 +1,19 @@
+pub mod accept;
+pub mod acme;
+pub mod activity;
+pub mod certificate;
+pub mod client_cert;
+pub mod client_ip;
 pub mod http;
+#[cfg(feature = "http3")]
+pub mod http3;
+pub mod limits;
+pub mod listener;
+pub mod ocsp;
+pub mod proxy_protocol;
+pub mod self_signed;
+pub mod stats;
+pub mod tls;
 
 mod logger;
 pub use logger::Logger;
@@ -6,4 +21,9 @@ pub use logger::Logger;
 mod macros;
 
 mod config;
-pub use config::{Config, SslConfig};
+pub use config::{
+    AcmeChallenge, AcmeConfig, CertificateConfig, ClientAuth, Config, ConnectionLimitAction,
+    ConnectionLimitConfig, HstsConfig, Http1Config, Http2Config, HttpMode, HttpProtocol, InheritFd,
+    ListenerConfig, ListenerKind, RedirectStatus, SslConfig, TcpSocketConfig, TimeoutConfig,
+    TlsVersion, Website,
+};
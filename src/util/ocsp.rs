@@ -0,0 +1,929 @@
+use std::{
+    fs,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use {
+    bytes::Bytes,
+    http_body_util::{BodyExt, Full},
+    hyper_util::{
+        client::legacy::{connect::HttpConnector, Client},
+        rt::TokioExecutor,
+    },
+    rustls_pki_types::CertificateDer,
+    sha1::{Digest, Sha1},
+    sha2::Sha256,
+    tokio::time::Instant,
+    tokio_util::sync::CancellationToken,
+    x509_parser::{
+        certificate::X509Certificate,
+        extensions::{GeneralName, ParsedExtension},
+        oid_registry::OID_PKIX_ACCESS_DESCRIPTOR_OCSP,
+        prelude::FromDer,
+        time::ASN1Time,
+    },
+};
+
+use crate::{
+    axum::http::{header::CONTENT_TYPE, Request},
+    tracing::{debug, warn},
+    util::{
+        certificate::system_time,
+        client_cert::sha256_hex,
+        config::SslConfig,
+        tls::{crypto_provider, CertResolver},
+    },
+    Error,
+};
+
+/// `id-pkix-ocsp-basic`, the only response type responders are required to support.
+const OCSP_BASIC: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01];
+/// The SHA-1 `AlgorithmIdentifier` used in request certificate IDs.
+const SHA1_ALGORITHM: &[u8] = &[
+    0x30, 0x09, 0x06, 0x05, 0x2b, 0x0e, 0x03, 0x02, 0x1a, 0x05, 0x00,
+];
+const SHA1_OID: &[u8] = &[0x2b, 0x0e, 0x03, 0x02, 0x1a];
+const SHA256_OID: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// The shortest wait between refreshes, even when a response is about to go stale.
+const MIN_REFRESH_DELAY: Duration = Duration::from_mins(1);
+
+/// A future resolving to a DER encoded OCSP response.
+pub type OcspFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<u8>, Error>> + Send + 'a>>;
+
+/// Fetches OCSP responses to staple to TLS handshakes.
+pub trait OcspFetcher: Send + Sync {
+    /// Fetch a DER encoded OCSP response for the first certificate of `chain`, which is
+    /// followed by its issuer.
+    fn fetch<'a>(&'a self, chain: &'a [CertificateDer<'static>]) -> OcspFuture<'a>;
+}
+
+/// Fetches OCSP responses over HTTP from the responder named in the certificate's
+/// authority information access extension.
+#[derive(Debug, Clone)]
+pub struct HttpOcspFetcher {
+    client: Client<HttpConnector, Full<Bytes>>,
+}
+
+impl HttpOcspFetcher {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            client: Client::builder(TokioExecutor::new()).build_http(),
+        }
+    }
+}
+
+impl Default for HttpOcspFetcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OcspFetcher for HttpOcspFetcher {
+    fn fetch<'a>(&'a self, chain: &'a [CertificateDer<'static>]) -> OcspFuture<'a> {
+        Box::pin(async move {
+            let [leaf, issuer, ..] = chain else {
+                return Err(Error::OcspError(
+                    "the certificate chain does not include the issuer".into(),
+                ));
+            };
+            let leaf = parse_certificate(leaf)?;
+            let issuer = parse_certificate(issuer)?;
+
+            let url = responder_url(&leaf).ok_or_else(|| {
+                Error::OcspError("the certificate does not name an OCSP responder".into())
+            })?;
+            let request = Request::post(&url)
+                .header(CONTENT_TYPE, "application/ocsp-request")
+                .body(Full::new(Bytes::from(ocsp_request(&leaf, &issuer))))
+                .map_err(|e| Error::OcspError(e.to_string()))?;
+
+            debug!("Fetching OCSP response from {}", url);
+            let response = tokio::time::timeout(FETCH_TIMEOUT, self.client.request(request))
+                .await
+                .map_err(|_| Error::OcspError(format!("{url} did not respond in time")))?
+                .map_err(|e| Error::OcspError(format!("{url}: {e}")))?;
+
+            if !response.status().is_success() {
+                return Err(Error::OcspError(format!(
+                    "{url} responded with {}",
+                    response.status()
+                )));
+            }
+
+            let body = response
+                .into_body()
+                .collect()
+                .await
+                .map_err(|e| Error::OcspError(format!("{url}: {e}")))?;
+            Ok(body.to_bytes().to_vec())
+        })
+    }
+}
+
+/// A checked OCSP response and the time after which it must no longer be stapled.
+#[derive(Debug, Clone)]
+pub(super) struct OcspStaple {
+    pub(super) response: Vec<u8>,
+    pub(super) next_update: Option<Instant>,
+}
+
+impl OcspStaple {
+    /// Check that `response` is a successful, current response reporting the first
+    /// certificate of `chain` as good, signed by its issuer, which must follow it, or by a
+    /// responder the issuer delegated to.
+    pub(super) fn parse(response: Vec<u8>, chain: &[CertificateDer]) -> Result<Self, String> {
+        let [leaf, issuer, ..] = chain else {
+            return Err("the certificate chain does not include the issuer".into());
+        };
+        let parse = |cert| {
+            X509Certificate::from_der(cert)
+                .map(|(_, cert)| cert)
+                .map_err(|e| e.to_string())
+        };
+        let next_update = check_response(&response, &parse(leaf)?, &parse(issuer)?)?;
+
+        Ok(Self {
+            response,
+            next_update: next_update.map(|next_update| {
+                Instant::now()
+                    + next_update
+                        .duration_since(SystemTime::now())
+                        .unwrap_or_default()
+            }),
+        })
+    }
+
+    pub(super) fn is_stale(&self) -> bool {
+        self.next_update
+            .is_some_and(|next_update| next_update <= Instant::now())
+    }
+}
+
+/// Staple OCSP responses to the certificates served by `resolver`, refreshing them every
+/// `ssl_config.ocsp_refresh_interval_secs` and dropping each once it passes its
+/// nextUpdate time, until `shutdown` is cancelled.
+///
+/// The default certificate's response is read from `ssl_config.ocsp_response_path` when
+/// set.  Other certificates, and the default one otherwise, use `fetcher`.
+pub fn spawn_ocsp_task(
+    resolver: Arc<CertResolver>,
+    ssl_config: &SslConfig,
+    fetcher: Option<Arc<dyn OcspFetcher>>,
+    shutdown: CancellationToken,
+) {
+    let response_path = ssl_config.ocsp_response_path.clone();
+    let interval = Duration::from_secs(ssl_config.ocsp_refresh_interval_secs);
+
+    tokio::spawn(async move {
+        loop {
+            refresh(&resolver, response_path.as_deref(), fetcher.as_deref()).await;
+
+            let delay = resolver
+                .expire_ocsp()
+                .map(|next_update| next_update.saturating_duration_since(Instant::now()))
+                .map_or(interval, |until_stale| until_stale.min(interval))
+                .max(MIN_REFRESH_DELAY);
+
+            tokio::select! {
+                () = tokio::time::sleep(delay) => {}
+                () = shutdown.cancelled() => break,
+            }
+        }
+    });
+}
+
+/// Load or fetch a response for every certificate, keeping the current staple of any
+/// which fails until it becomes stale.
+async fn refresh(
+    resolver: &CertResolver,
+    response_path: Option<&str>,
+    fetcher: Option<&dyn OcspFetcher>,
+) {
+    for (i, chain) in resolver.chains().into_iter().enumerate() {
+        let response = match (response_path, fetcher) {
+            (Some(path), _) if i == 0 => fs::read(path).map_err(|source| Error::TlsFileError {
+                path: path.to_string(),
+                source,
+            }),
+            (_, Some(fetcher)) => fetcher.fetch(&chain).await,
+            _ => continue,
+        };
+
+        let fingerprint = sha256_hex(&chain[0]);
+        match response
+            .and_then(|response| OcspStaple::parse(response, &chain).map_err(Error::OcspError))
+        {
+            Ok(staple) => {
+                debug!("Stapling OCSP response for certificate {}", fingerprint);
+                resolver.set_ocsp(&fingerprint, staple);
+            }
+            Err(e) => warn!(
+                "Cannot staple an OCSP response for certificate {}: {}",
+                fingerprint, e
+            ),
+        }
+    }
+}
+
+fn parse_certificate<'a>(cert: &'a CertificateDer) -> Result<X509Certificate<'a>, Error> {
+    X509Certificate::from_der(cert)
+        .map(|(_, cert)| cert)
+        .map_err(|e| Error::OcspError(e.to_string()))
+}
+
+fn responder_url(cert: &X509Certificate) -> Option<String> {
+    cert.extensions()
+        .iter()
+        .find_map(|extension| match extension.parsed_extension() {
+            ParsedExtension::AuthorityInfoAccess(aia) => {
+                aia.accessdescs
+                    .iter()
+                    .find_map(|description| match &description.access_location {
+                        GeneralName::URI(uri)
+                            if description.access_method == OID_PKIX_ACCESS_DESCRIPTOR_OCSP =>
+                        {
+                            Some((*uri).to_string())
+                        }
+                        _ => None,
+                    })
+            }
+            _ => None,
+        })
+}
+
+/// An `OCSPRequest` for `leaf` without a nonce, so responders may serve it from a cache.
+fn ocsp_request(leaf: &X509Certificate, issuer: &X509Certificate) -> Vec<u8> {
+    let (name_hash, key_hash) = issuer_hashes::<Sha1>(issuer);
+    let cert_id = [
+        SHA1_ALGORITHM,
+        &der(0x04, &name_hash),
+        &der(0x04, &key_hash),
+        &der(0x02, leaf.raw_serial()),
+    ]
+    .concat();
+
+    // OCSPRequest { TBSRequest { requestList { Request { CertID } } } }
+    let request = der(0x30, &cert_id);
+    let request_list = der(0x30, &der(0x30, &request));
+    der(0x30, &der(0x30, &request_list))
+}
+
+/// The hashes of an issuer's name and public key which identify it in certificate IDs.
+fn issuer_hashes<D: Digest>(issuer: &X509Certificate) -> (Vec<u8>, Vec<u8>) {
+    (
+        D::digest(issuer.subject().as_raw()).to_vec(),
+        D::digest(&issuer.public_key().subject_public_key.data).to_vec(),
+    )
+}
+
+/// Encode a DER value with the given tag.
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    match u8::try_from(content.len()) {
+        Ok(len) if len < 0x80 => out.push(len),
+        _ => {
+            let len = content.len().to_be_bytes();
+            let significant = &len[len.iter().take_while(|&&b| b == 0).count()..];
+            out.push(0x80 | u8::try_from(significant.len()).unwrap_or(0));
+            out.extend_from_slice(significant);
+        }
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// DER encoded data with its signature and the contents of the signature's
+/// `AlgorithmIdentifier`.
+struct Signed<'a> {
+    data: &'a [u8],
+    algorithm: &'a [u8],
+    signature: &'a [u8],
+}
+
+/// Reads consecutive DER values.
+struct DerReader<'a>(&'a [u8]);
+
+impl<'a> DerReader<'a> {
+    const fn peek_tag(&self) -> Option<u8> {
+        self.0.first().copied()
+    }
+
+    fn read(&mut self) -> Result<(u8, &'a [u8]), String> {
+        let truncated = || "truncated OCSP response".to_string();
+
+        let (&tag, rest) = self.0.split_first().ok_or_else(truncated)?;
+        let (&first, rest) = rest.split_first().ok_or_else(truncated)?;
+        let (len, rest) = if first < 0x80 {
+            (usize::from(first), rest)
+        } else {
+            let count = usize::from(first & 0x7f);
+            if count > std::mem::size_of::<usize>() || rest.len() < count {
+                return Err(truncated());
+            }
+            let (bytes, rest) = rest.split_at(count);
+            let len = bytes
+                .iter()
+                .fold(0usize, |len, &b| (len << 8) | usize::from(b));
+            (len, rest)
+        };
+
+        if rest.len() < len {
+            return Err(truncated());
+        }
+        let (content, rest) = rest.split_at(len);
+        self.0 = rest;
+        Ok((tag, content))
+    }
+
+    /// Read the next value including its tag and length.
+    fn read_raw(&mut self) -> Result<&'a [u8], String> {
+        let start = self.0;
+        self.read()?;
+        Ok(&start[..start.len() - self.0.len()])
+    }
+
+    /// Read the signed data and signature which start both certificates and basic OCSP
+    /// responses.
+    fn read_signed(&mut self) -> Result<Signed<'a>, String> {
+        let data = self.read_raw()?;
+        let algorithm = self.expect(0x30)?;
+        match self.expect(0x03)? {
+            [0, signature @ ..] => Ok(Signed {
+                data,
+                algorithm,
+                signature,
+            }),
+            _ => Err("invalid signature in OCSP response".into()),
+        }
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<&'a [u8], String> {
+        match self.read()? {
+            (tag, content) if tag == expected => Ok(content),
+            (tag, _) => Err(format!(
+                "unexpected tag {tag:#04x} in OCSP response, expected {expected:#04x}"
+            )),
+        }
+    }
+}
+
+/// Check an `OCSPResponse` for `leaf`, signed by `issuer` or a responder it delegated to,
+/// returning its nextUpdate.
+fn check_response(
+    response: &[u8],
+    leaf: &X509Certificate,
+    issuer: &X509Certificate,
+) -> Result<Option<SystemTime>, String> {
+    let mut response = DerReader(DerReader(response).expect(0x30)?);
+    match response.expect(0x0a)? {
+        [0] => {}
+        status => return Err(format!("the responder returned status {status:?}")),
+    }
+
+    let mut response_bytes = DerReader(DerReader(response.expect(0xa0)?).expect(0x30)?);
+    if response_bytes.expect(0x06)? != OCSP_BASIC {
+        return Err("unsupported OCSP response type".into());
+    }
+    let mut basic = DerReader(DerReader(response_bytes.expect(0x04)?).expect(0x30)?);
+    let signed = basic.read_signed()?;
+    let certs = if basic.peek_tag() == Some(0xa0) {
+        DerReader(DerReader(basic.expect(0xa0)?).expect(0x30)?)
+    } else {
+        DerReader(&[])
+    };
+    check_signer(&signed, issuer, certs)?;
+
+    let mut response_data = DerReader(DerReader(signed.data).expect(0x30)?);
+
+    if response_data.peek_tag() == Some(0xa0) {
+        response_data.read()?;
+    }
+    response_data.read()?;
+    response_data.expect(0x18)?;
+    let mut responses = DerReader(response_data.expect(0x30)?);
+
+    let now = SystemTime::now();
+    while responses.peek_tag().is_some() {
+        let mut single = DerReader(responses.expect(0x30)?);
+        let mut cert_id = DerReader(single.expect(0x30)?);
+        let hash_algorithm = DerReader(cert_id.expect(0x30)?).expect(0x06)?;
+        let issuer_name_hash = cert_id.expect(0x04)?;
+        let issuer_key_hash = cert_id.expect(0x04)?;
+        if cert_id.expect(0x02)? != leaf.raw_serial() {
+            continue;
+        }
+        let (name_hash, key_hash) = match hash_algorithm {
+            SHA1_OID => issuer_hashes::<Sha1>(issuer),
+            SHA256_OID => issuer_hashes::<Sha256>(issuer),
+            _ => continue,
+        };
+        if issuer_name_hash != name_hash || issuer_key_hash != key_hash {
+            continue;
+        }
+
+        match single.read()?.0 {
+            0x80 => {}
+            0xa1 => return Err("the certificate has been revoked".into()),
+            _ => return Err("the responder does not know the certificate".into()),
+        }
+
+        let this_update = time(single.expect(0x18)?)?;
+        if this_update > now {
+            return Err("the response is not yet valid".into());
+        }
+
+        if single.peek_tag() != Some(0xa0) {
+            return Ok(None);
+        }
+        let next_update = time(DerReader(single.expect(0xa0)?).expect(0x18)?)?;
+        if next_update <= now {
+            return Err("the response is stale".into());
+        }
+        return Ok(Some(next_update));
+    }
+
+    Err("the response does not cover the certificate".into())
+}
+
+/// Check that a basic OCSP response was signed by `issuer`, or by a current certificate in
+/// `certs` which `issuer` issued for OCSP signing.
+fn check_signer(
+    signed: &Signed,
+    issuer: &X509Certificate,
+    mut certs: DerReader,
+) -> Result<(), String> {
+    if verify_signature(signed, issuer) {
+        return Ok(());
+    }
+
+    while certs.peek_tag().is_some() {
+        let responder = certs.read_raw()?;
+        if delegated_responder(responder, issuer)
+            .is_some_and(|responder| verify_signature(signed, &responder))
+        {
+            return Ok(());
+        }
+    }
+
+    Err("the response is not signed by the issuer or a responder it authorized".into())
+}
+
+/// Parse `cert` if it is a current OCSP signing certificate issued by `issuer`.
+fn delegated_responder<'a>(
+    cert: &'a [u8],
+    issuer: &X509Certificate,
+) -> Option<X509Certificate<'a>> {
+    let (_, responder) = X509Certificate::from_der(cert).ok()?;
+    let signed = DerReader(DerReader(cert).expect(0x30).ok()?)
+        .read_signed()
+        .ok()?;
+
+    let authorized = responder.issuer().as_raw() == issuer.subject().as_raw()
+        && responder.validity().is_valid()
+        && responder
+            .extended_key_usage()
+            .ok()
+            .flatten()
+            .is_some_and(|usage| usage.value.ocsp_signing)
+        && verify_signature(&signed, issuer);
+    authorized.then_some(responder)
+}
+
+/// Whether `signed` verifies with the public key of `cert`.
+fn verify_signature(signed: &Signed, cert: &X509Certificate) -> bool {
+    let Ok(key_algorithm) = DerReader(cert.public_key().raw)
+        .expect(0x30)
+        .and_then(|spki| DerReader(spki).expect(0x30))
+    else {
+        return false;
+    };
+    let key = &cert.public_key().subject_public_key.data;
+
+    crypto_provider()
+        .signature_verification_algorithms
+        .all
+        .iter()
+        .filter(|verifier| {
+            verifier.public_key_alg_id().as_ref() == key_algorithm
+                && verifier.signature_alg_id().as_ref() == signed.algorithm
+        })
+        .any(|verifier| {
+            verifier
+                .verify_signature(key, signed.data, signed.signature)
+                .is_ok()
+        })
+}
+
+fn time(generalized_time: &[u8]) -> Result<SystemTime, String> {
+    ASN1Time::from_der(&der(0x18, generalized_time))
+        .map(|(_, time)| system_time(time))
+        .map_err(|e| format!("invalid time in OCSP response: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Mutex};
+
+    use {
+        rcgen::{
+            BasicConstraints, Certificate, CertificateParams, CustomExtension, DnType,
+            ExtendedKeyUsagePurpose, IsCa, KeyPair, PKCS_ECDSA_P256_SHA256,
+        },
+        rustls_pki_types::PrivatePkcs8KeyDer,
+        time::OffsetDateTime,
+        tokio::net::TcpListener,
+        tokio_rustls::rustls::SignatureScheme,
+    };
+
+    use super::*;
+    use crate::axum::{body::Bytes as Body, extract::State, routing::post, Router};
+
+    const GOOD: u8 = 0x80;
+    const REVOKED: u8 = 0xa1;
+
+    fn generalized_time(time: OffsetDateTime) -> Vec<u8> {
+        let formatted = format!(
+            "{:04}{:02}{:02}{:02}{:02}{:02}Z",
+            time.year(),
+            u8::from(time.month()),
+            time.day(),
+            time.hour(),
+            time.minute(),
+            time.second()
+        );
+        der(0x18, formatted.as_bytes())
+    }
+
+    /// The `ecdsa-with-SHA256` `AlgorithmIdentifier` of test signatures.
+    const ECDSA_SHA256: &[u8] = &[
+        0x30, 0x0a, 0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02,
+    ];
+
+    /// Signs OCSP responses with `key`, including `certs` in them.
+    struct Responder<'a> {
+        key: &'a KeyPair,
+        certs: Vec<CertificateDer<'static>>,
+    }
+
+    impl<'a> Responder<'a> {
+        const fn new(key: &'a KeyPair) -> Self {
+            Self {
+                key,
+                certs: Vec::new(),
+            }
+        }
+
+        fn sign(&self, message: &[u8]) -> Vec<u8> {
+            let key = PrivatePkcs8KeyDer::from(self.key.serialize_der());
+            crypto_provider()
+                .key_provider
+                .load_private_key(key.into())
+                .unwrap()
+                .choose_scheme(&[SignatureScheme::ECDSA_NISTP256_SHA256])
+                .unwrap()
+                .sign(message)
+                .unwrap()
+        }
+    }
+
+    /// The SHA-1 `CertID` of the certificate with `serial` issued by the second certificate
+    /// of `chain`.
+    fn cert_id(chain: &[CertificateDer], serial: &[u8]) -> Vec<u8> {
+        let (_, issuer) = X509Certificate::from_der(&chain[1]).unwrap();
+        let (name_hash, key_hash) = issuer_hashes::<Sha1>(&issuer);
+        der(
+            0x30,
+            &[
+                SHA1_ALGORITHM,
+                &der(0x04, &name_hash),
+                &der(0x04, &key_hash),
+                &der(0x02, serial),
+            ]
+            .concat(),
+        )
+    }
+
+    /// An `OCSPResponse` from `responder` reporting `status` for `cert_id`.
+    fn ocsp_response(
+        responder: &Responder,
+        cert_id: Vec<u8>,
+        status: u8,
+        next_update: Option<OffsetDateTime>,
+    ) -> Vec<u8> {
+        let now = OffsetDateTime::now_utc();
+        let cert_status = if status == REVOKED {
+            der(REVOKED, &generalized_time(now))
+        } else {
+            der(status, &[])
+        };
+        let next_update = next_update
+            .map(|time| der(0xa0, &generalized_time(time)))
+            .unwrap_or_default();
+        let single = der(
+            0x30,
+            &[
+                cert_id,
+                cert_status,
+                generalized_time(now - time::Duration::hours(1)),
+                next_update,
+            ]
+            .concat(),
+        );
+
+        let response_data = der(
+            0x30,
+            &[
+                der(0xa2, &der(0x04, &[0; 20])),
+                generalized_time(now),
+                der(0x30, &single),
+            ]
+            .concat(),
+        );
+        let certs = if responder.certs.is_empty() {
+            Vec::new()
+        } else {
+            let certs: Vec<&[u8]> = responder.certs.iter().map(AsRef::as_ref).collect();
+            der(0xa0, &der(0x30, &certs.concat()))
+        };
+        let basic = der(
+            0x30,
+            &[
+                ECDSA_SHA256,
+                &der(0x03, &[&[0], &responder.sign(&response_data)[..]].concat()),
+                &certs,
+            ]
+            .iter()
+            .fold(response_data, |basic, part| [&basic[..], part].concat()),
+        );
+        let response_bytes = der(0x30, &[der(0x06, OCSP_BASIC), der(0x04, &basic)].concat());
+
+        der(
+            0x30,
+            &[der(0x0a, &[0]), der(0xa0, &response_bytes)].concat(),
+        )
+    }
+
+    struct TestChain {
+        chain: Vec<CertificateDer<'static>>,
+        chain_pem: String,
+        key_pem: String,
+        ca: Certificate,
+        ca_key: KeyPair,
+    }
+
+    impl TestChain {
+        /// A response for the leaf signed by the CA.
+        fn response(&self, status: u8, next_update: Option<OffsetDateTime>) -> Vec<u8> {
+            ocsp_response(
+                &Responder::new(&self.ca_key),
+                cert_id(&self.chain, &serial(&self.chain[0])),
+                status,
+                next_update,
+            )
+        }
+
+        /// A responder certificate issued by the CA, for OCSP signing when `ocsp_signing`.
+        fn delegate(&self, key: &KeyPair, ocsp_signing: bool) -> CertificateDer<'static> {
+            let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+            params
+                .distinguished_name
+                .push(DnType::CommonName, "grafton OCSP responder");
+            if ocsp_signing {
+                params.extended_key_usages = vec![ExtendedKeyUsagePurpose::OcspSigning];
+            }
+            params
+                .signed_by(key, &self.ca, &self.ca_key)
+                .unwrap()
+                .der()
+                .clone()
+        }
+
+        /// A resolver loading the chain from files, removed again by `cleanup`.
+        fn resolver(&self, name: &str) -> (CertResolver, PathBuf) {
+            let dir =
+                std::env::temp_dir().join(format!("grafton-ocsp-{name}-{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("cert.pem"), &self.chain_pem).unwrap();
+            fs::write(dir.join("key.pem"), &self.key_pem).unwrap();
+
+            let ssl_config = SslConfig {
+                cert_path: dir.join("cert.pem").to_string_lossy().into_owned(),
+                key_path: dir.join("key.pem").to_string_lossy().into_owned(),
+                ..Default::default()
+            };
+            (CertResolver::new(&ssl_config).unwrap(), dir)
+        }
+    }
+
+    /// A CA and a leaf naming `responder` as its OCSP responder.
+    fn test_chain(responder: &str) -> TestChain {
+        let ca_key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params
+            .distinguished_name
+            .push(DnType::CommonName, "grafton OCSP test CA");
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        let ocsp_access = der(
+            0x30,
+            &[
+                der(0x06, &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01]),
+                der(0x86, responder.as_bytes()),
+            ]
+            .concat(),
+        );
+        let mut leaf_params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+        leaf_params
+            .custom_extensions
+            .push(CustomExtension::from_oid_content(
+                &[1, 3, 6, 1, 5, 5, 7, 1, 1],
+                der(0x30, &ocsp_access),
+            ));
+        let leaf_key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+        let leaf = leaf_params.signed_by(&leaf_key, &ca, &ca_key).unwrap();
+
+        TestChain {
+            chain: vec![leaf.der().clone(), ca.der().clone()],
+            chain_pem: leaf.pem() + &ca.pem(),
+            key_pem: leaf_key.serialize_pem(),
+            ca,
+            ca_key,
+        }
+    }
+
+    fn serial(cert: &CertificateDer) -> Vec<u8> {
+        X509Certificate::from_der(cert)
+            .unwrap()
+            .1
+            .raw_serial()
+            .to_vec()
+    }
+
+    #[test]
+    fn responses_must_be_good_and_current() {
+        let test_chain = test_chain("http://ocsp.example");
+        let chain = &test_chain.chain;
+        let tomorrow = OffsetDateTime::now_utc() + time::Duration::days(1);
+        let yesterday = OffsetDateTime::now_utc() - time::Duration::days(1);
+
+        let staple = OcspStaple::parse(test_chain.response(GOOD, Some(tomorrow)), chain).unwrap();
+        assert!(staple.next_update.is_some());
+        assert!(!staple.is_stale());
+
+        let staple = OcspStaple::parse(test_chain.response(GOOD, None), chain).unwrap();
+        assert!(staple.next_update.is_none());
+
+        let revoked = test_chain.response(REVOKED, Some(tomorrow));
+        assert!(OcspStaple::parse(revoked, chain)
+            .unwrap_err()
+            .contains("revoked"));
+
+        let expired = test_chain.response(GOOD, Some(yesterday));
+        assert!(OcspStaple::parse(expired, chain)
+            .unwrap_err()
+            .contains("stale"));
+
+        let responder = Responder::new(&test_chain.ca_key);
+        let other = ocsp_response(&responder, cert_id(chain, &[0x01]), GOOD, Some(tomorrow));
+        assert!(OcspStaple::parse(other, chain)
+            .unwrap_err()
+            .contains("does not cover"));
+
+        let leaf_only = test_chain.response(GOOD, Some(tomorrow));
+        assert!(OcspStaple::parse(leaf_only, &chain[..1])
+            .unwrap_err()
+            .contains("issuer"));
+    }
+
+    #[test]
+    fn responses_must_be_signed_for_the_issuer() {
+        let other_ca = test_chain("http://ocsp.example");
+        let test_chain = test_chain("http://ocsp.example");
+        let chain = &test_chain.chain;
+        let serial = serial(&chain[0]);
+        let tomorrow = Some(OffsetDateTime::now_utc() + time::Duration::days(1));
+
+        let mut corrupted = test_chain.response(GOOD, tomorrow);
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 1;
+        assert!(OcspStaple::parse(corrupted, chain)
+            .unwrap_err()
+            .contains("not signed"));
+
+        let forged = ocsp_response(
+            &Responder::new(&other_ca.ca_key),
+            cert_id(chain, &serial),
+            GOOD,
+            tomorrow,
+        );
+        assert!(OcspStaple::parse(forged, chain)
+            .unwrap_err()
+            .contains("not signed"));
+
+        let wrong_issuer = ocsp_response(
+            &Responder::new(&test_chain.ca_key),
+            cert_id(&other_ca.chain, &serial),
+            GOOD,
+            tomorrow,
+        );
+        assert!(OcspStaple::parse(wrong_issuer, chain)
+            .unwrap_err()
+            .contains("does not cover"));
+    }
+
+    #[test]
+    fn delegated_responders_need_ocsp_signing() {
+        let test_chain = test_chain("http://ocsp.example");
+        let chain = &test_chain.chain;
+        let cert_id = cert_id(chain, &serial(&chain[0]));
+        let tomorrow = Some(OffsetDateTime::now_utc() + time::Duration::days(1));
+        let key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+
+        let delegated = Responder {
+            key: &key,
+            certs: vec![test_chain.delegate(&key, true)],
+        };
+        let response = ocsp_response(&delegated, cert_id.clone(), GOOD, tomorrow);
+        assert!(OcspStaple::parse(response, chain).is_ok());
+
+        let unauthorized = Responder {
+            key: &key,
+            certs: vec![test_chain.delegate(&key, false)],
+        };
+        let response = ocsp_response(&unauthorized, cert_id, GOOD, tomorrow);
+        assert!(OcspStaple::parse(response, chain)
+            .unwrap_err()
+            .contains("not signed"));
+    }
+
+    #[tokio::test]
+    async fn http_fetcher_posts_to_the_responder() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let responder = format!("http://{}/ocsp", listener.local_addr().unwrap());
+        let test_chain = test_chain(&responder);
+        let chain = &test_chain.chain;
+        let serial = serial(&chain[0]);
+
+        let response = test_chain.response(
+            GOOD,
+            Some(OffsetDateTime::now_utc() + time::Duration::days(1)),
+        );
+        let received: Arc<Mutex<Vec<Body>>> = Arc::default();
+        let canned = response.clone();
+        let router = Router::new()
+            .route(
+                "/ocsp",
+                post(
+                    move |State(received): State<Arc<Mutex<Vec<Body>>>>, body: Body| {
+                        let response = canned.clone();
+                        async move {
+                            received.lock().unwrap().push(body);
+                            response
+                        }
+                    },
+                ),
+            )
+            .with_state(received.clone());
+        tokio::spawn(async move { crate::axum::serve(listener, router).await });
+
+        let fetched = HttpOcspFetcher::new().fetch(chain).await.unwrap();
+        assert_eq!(fetched, response);
+
+        let ocsp_request = received.lock().unwrap().pop().unwrap();
+        assert!(ocsp_request.windows(serial.len()).any(|w| w == serial));
+
+        let (resolver, dir) = test_chain.resolver("fetch");
+        refresh(&resolver, None, Some(&HttpOcspFetcher::new())).await;
+        assert_eq!(resolver.stapled_response(), Some(response));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn response_file_is_stapled_to_the_default_certificate() {
+        let test_chain = test_chain("http://ocsp.example");
+        let (resolver, dir) = test_chain.resolver("file");
+        let path = dir.join("ocsp.der");
+        let response = test_chain.response(
+            GOOD,
+            Some(OffsetDateTime::now_utc() + time::Duration::seconds(2)),
+        );
+        fs::write(&path, &response).unwrap();
+
+        refresh(&resolver, path.to_str(), None).await;
+        assert_eq!(resolver.stapled_response(), Some(response));
+
+        tokio::time::advance(Duration::from_secs(3)).await;
+        assert!(resolver.expire_ocsp().is_none());
+        assert_eq!(resolver.stapled_response(), None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use {
+    ipnet::IpNet,
+    tokio::io::{AsyncRead, AsyncReadExt},
+};
+
+use crate::util::listener::PeerAddr;
+
+/// The signature which starts every PROXY protocol v2 header.
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The longest possible PROXY protocol v1 header, including the trailing CRLF.
+const V1_MAX_LENGTH: usize = 107;
+
+/// How long a load balancer has to send the PROXY protocol header.
+pub const HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The original client and destination addresses a load balancer reported in a PROXY
+/// protocol header.
+///
+/// Inserted into every request on a proxied connection as `ConnectInfo<ProxyHeader>`,
+/// while `ConnectInfo<PeerAddr>` carries the client address in place of the load
+/// balancer's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProxyHeader {
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+}
+
+/// Reads PROXY protocol headers on a listener, accepting them only from trusted networks.
+#[derive(Debug, Clone, Default)]
+pub struct ProxyProtocol {
+    trusted: Vec<IpNet>,
+}
+
+impl ProxyProtocol {
+    /// Accept headers from peers in `trusted`.  No TCP peer is trusted when it is empty.
+    #[must_use]
+    pub const fn new(trusted: Vec<IpNet>) -> Self {
+        Self { trusted }
+    }
+
+    /// Whether `peer` may send a PROXY protocol header.  Unix domain socket peers are
+    /// local and always trusted.
+    #[must_use]
+    pub fn trusts(&self, peer: &PeerAddr) -> bool {
+        match peer {
+            PeerAddr::Tcp(addr) => {
+                let ip = addr.ip().to_canonical();
+                self.trusted.iter().any(|net| net.contains(&ip))
+            }
+            PeerAddr::Unix(_) => true,
+        }
+    }
+}
+
+/// Read a PROXY protocol v1 or v2 header from the start of `io`, leaving the connection
+/// positioned at the first byte after it.
+///
+/// Returns `None` for headers which carry no addresses, such as the `LOCAL` command load
+/// balancers use for health checks.
+///
+/// # Errors
+///
+/// Returns an `InvalidData` error if the connection does not start with a valid header.
+pub async fn read_header<I>(io: &mut I) -> io::Result<Option<ProxyHeader>>
+where
+    I: AsyncRead + Unpin,
+{
+    let mut prefix = [0; 6];
+    io.read_exact(&mut prefix).await?;
+
+    if &prefix == b"PROXY " {
+        let mut line = prefix.to_vec();
+        while !line.ends_with(b"\r\n") {
+            if line.len() == V1_MAX_LENGTH {
+                return Err(invalid("PROXY protocol v1 header is too long"));
+            }
+            line.push(io.read_u8().await?);
+        }
+        parse_v1(&line)
+    } else if prefix == V2_SIGNATURE[..6] {
+        let mut header = [0; 16];
+        header[..6].copy_from_slice(&prefix);
+        io.read_exact(&mut header[6..]).await?;
+        if header[..12] != V2_SIGNATURE {
+            return Err(invalid("invalid PROXY protocol v2 signature"));
+        }
+
+        let mut addresses = vec![0; usize::from(u16::from_be_bytes([header[14], header[15]]))];
+        io.read_exact(&mut addresses).await?;
+        parse_v2(header[12], header[13], &addresses)
+    } else {
+        Err(invalid(
+            "connection does not start with a PROXY protocol header",
+        ))
+    }
+}
+
+fn parse_v1(line: &[u8]) -> io::Result<Option<ProxyHeader>> {
+    let line = std::str::from_utf8(&line[..line.len() - 2])
+        .map_err(|_| invalid("PROXY protocol v1 header is not ASCII"))?;
+    let fields: Vec<&str> = line.split(' ').collect();
+
+    match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", protocol @ ("TCP4" | "TCP6"), source, destination, source_port, destination_port] =>
+        {
+            let ip = |field: &str| {
+                let ip: IpAddr = field
+                    .parse()
+                    .map_err(|_| invalid("invalid address in PROXY protocol v1 header"))?;
+                if ip.is_ipv4() == (*protocol == "TCP4") {
+                    Ok(ip)
+                } else {
+                    Err(invalid(
+                        "address family mismatch in PROXY protocol v1 header",
+                    ))
+                }
+            };
+            let port = |field: &str| {
+                field
+                    .parse::<u16>()
+                    .map_err(|_| invalid("invalid port in PROXY protocol v1 header"))
+            };
+
+            Ok(Some(ProxyHeader {
+                source: SocketAddr::new(ip(source)?, port(source_port)?),
+                destination: SocketAddr::new(ip(destination)?, port(destination_port)?),
+            }))
+        }
+        _ => Err(invalid("malformed PROXY protocol v1 header")),
+    }
+}
+
+fn parse_v2(version_command: u8, family: u8, addresses: &[u8]) -> io::Result<Option<ProxyHeader>> {
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+    match version_command & 0x0f {
+        0 => return Ok(None),
+        1 => {}
+        _ => return Err(invalid("unsupported PROXY protocol v2 command")),
+    }
+
+    let header = match family >> 4 {
+        1 if addresses.len() >= 12 => {
+            let ip = |offset: usize| {
+                IpAddr::V4(Ipv4Addr::from(
+                    <[u8; 4]>::try_from(&addresses[offset..offset + 4]).unwrap_or_default(),
+                ))
+            };
+            let port =
+                |offset: usize| u16::from_be_bytes([addresses[offset], addresses[offset + 1]]);
+            ProxyHeader {
+                source: SocketAddr::new(ip(0), port(8)),
+                destination: SocketAddr::new(ip(4), port(10)),
+            }
+        }
+        2 if addresses.len() >= 36 => {
+            let ip = |offset: usize| {
+                IpAddr::V6(Ipv6Addr::from(
+                    <[u8; 16]>::try_from(&addresses[offset..offset + 16]).unwrap_or_default(),
+                ))
+            };
+            let port =
+                |offset: usize| u16::from_be_bytes([addresses[offset], addresses[offset + 1]]);
+            ProxyHeader {
+                source: SocketAddr::new(ip(0), port(32)),
+                destination: SocketAddr::new(ip(16), port(34)),
+            }
+        }
+        1 | 2 => return Err(invalid("truncated PROXY protocol v2 addresses")),
+        // Unspecified and unix socket addresses say nothing about the client's IP.
+        _ => return Ok(None),
+    };
+
+    Ok(Some(header))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read(mut input: &[u8]) -> (io::Result<Option<ProxyHeader>>, &[u8]) {
+        let header = read_header(&mut input).await;
+        (header, input)
+    }
+
+    fn v2(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend([0x20 | command, family]);
+        header.extend(u16::try_from(addresses.len()).unwrap().to_be_bytes());
+        header.extend(addresses);
+        header.extend(b"GET / HTTP/1.1\r\n");
+        header
+    }
+
+    #[tokio::test]
+    async fn reads_v1_header_and_leaves_payload() {
+        let (header, rest) = read(b"PROXY TCP4 192.0.2.1 198.51.100.2 51000 443\r\nGET /").await;
+
+        let header = header.unwrap().unwrap();
+        assert_eq!(header.source, "192.0.2.1:51000".parse().unwrap());
+        assert_eq!(header.destination, "198.51.100.2:443".parse().unwrap());
+        assert_eq!(rest, b"GET /");
+
+        let (header, _) = read(b"PROXY TCP6 2001:db8::1 2001:db8::2 51000 443\r\n").await;
+        assert_eq!(
+            header.unwrap().unwrap().source,
+            "[2001:db8::1]:51000".parse().unwrap()
+        );
+
+        let (header, rest) = read(b"PROXY UNKNOWN\r\nGET /").await;
+        assert_eq!(header.unwrap(), None);
+        assert_eq!(rest, b"GET /");
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_v1_headers() {
+        for input in [
+            &b"PROXY TCP4 192.0.2.1 198.51.100.2 51000\r\n"[..],
+            b"PROXY TCP4 2001:db8::1 198.51.100.2 51000 443\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 51000 70000\r\n",
+            b"GET / HTTP/1.1\r\n",
+        ] {
+            let (header, _) = read(input).await;
+            assert_eq!(header.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+
+        let mut too_long = b"PROXY ".to_vec();
+        too_long.resize(200, b'1');
+        let (header, _) = read(&too_long).await;
+        assert_eq!(header.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn reads_v2_header_and_leaves_payload() {
+        let input = v2(
+            1,
+            0x11,
+            &[
+                192, 0, 2, 1, 198, 51, 100, 2, 0xc7, 0x38, 0x01, 0xbb, 0x04, 0x00,
+            ],
+        );
+        let (header, rest) = read(&input).await;
+
+        let header = header.unwrap().unwrap();
+        assert_eq!(header.source, "192.0.2.1:51000".parse().unwrap());
+        assert_eq!(header.destination, "198.51.100.2:443".parse().unwrap());
+        assert_eq!(rest, b"GET / HTTP/1.1\r\n");
+
+        let mut addresses = Ipv6Addr::LOCALHOST.octets().to_vec();
+        addresses.extend(Ipv6Addr::UNSPECIFIED.octets());
+        addresses.extend([0xc7, 0x38, 0x01, 0xbb]);
+        let (header, _) = read(&v2(1, 0x21, &addresses)).await;
+        assert_eq!(
+            header.unwrap().unwrap().source,
+            "[::1]:51000".parse().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn v2_local_command_carries_no_addresses() {
+        let input = v2(0, 0x00, &[]);
+        let (header, rest) = read(&input).await;
+
+        assert_eq!(header.unwrap(), None);
+        assert_eq!(rest, b"GET / HTTP/1.1\r\n");
+    }
+
+    #[tokio::test]
+    async fn rejects_truncated_v2_addresses() {
+        let (header, _) = read(&v2(1, 0x11, &[192, 0, 2, 1])).await;
+        assert_eq!(header.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn trusts_only_configured_networks() {
+        let proxy = ProxyProtocol::new(vec!["10.0.0.0/8".parse().unwrap()]);
+
+        assert!(proxy.trusts(&PeerAddr::Tcp("10.1.2.3:1000".parse().unwrap())));
+        assert!(proxy.trusts(&PeerAddr::Tcp("[::ffff:10.1.2.3]:1000".parse().unwrap())));
+        assert!(!proxy.trusts(&PeerAddr::Tcp("192.0.2.1:1000".parse().unwrap())));
+        assert!(!ProxyProtocol::default().trusts(&PeerAddr::Tcp("192.0.2.1:1000".parse().unwrap())));
+    }
+}
@@ -0,0 +1,162 @@
+use std::{fs, io, net::IpAddr, path::Path};
+
+use {
+    rcgen::{Certificate, CertificateParams, DistinguishedName, DnType, KeyPair, SanType},
+    time::{Duration, OffsetDateTime},
+};
+
+use crate::{
+    tracing::{info, warn},
+    util::{client_cert::sha256_hex, config::SslConfig},
+    Error,
+};
+
+/// Validity of generated certificates, kept under the 825 day limit some platforms
+/// enforce for TLS server certificates.
+const VALIDITY_DAYS: i64 = 365;
+
+/// Generate a self-signed certificate for `hostname`, `localhost` and the loopback
+/// addresses and write it to the configured certificate and key paths.
+///
+/// Does nothing when either file already exists, so an existing certificate or key is
+/// never overwritten.
+///
+/// # Errors
+///
+/// Returns an error if the certificate cannot be generated or written.
+pub fn ensure_self_signed(ssl_config: &SslConfig, hostname: &str) -> Result<(), Error> {
+    let cert_path = Path::new(&ssl_config.cert_path);
+    let key_path = Path::new(&ssl_config.key_path);
+
+    match (cert_path.exists(), key_path.exists()) {
+        (false, false) => {}
+        (true, true) => return Ok(()),
+        _ => {
+            warn!(
+                "Only one of {} and {} exists, not generating a self-signed certificate",
+                ssl_config.cert_path, ssl_config.key_path
+            );
+            return Ok(());
+        }
+    }
+
+    let (cert, key_pair) = generate(hostname)?;
+
+    write_file(key_path, key_pair.serialize_pem().as_bytes(), 0o600).map_err(|source| {
+        Error::TlsFileError {
+            path: ssl_config.key_path.clone(),
+            source,
+        }
+    })?;
+    write_file(cert_path, cert.pem().as_bytes(), 0o644).map_err(|source| Error::TlsFileError {
+        path: ssl_config.cert_path.clone(),
+        source,
+    })?;
+
+    info!(
+        "Generated a self-signed development certificate for {} at {}, SHA-256 fingerprint {}",
+        hostname,
+        ssl_config.cert_path,
+        sha256_hex(cert.der())
+    );
+
+    Ok(())
+}
+
+/// Generate a self-signed certificate for `hostname`, `localhost` and the loopback
+/// addresses, without saving it.
+pub(super) fn generate(hostname: &str) -> Result<(Certificate, KeyPair), Error> {
+    let key_pair = KeyPair::generate()?;
+    let cert = self_signed_params(hostname).self_signed(&key_pair)?;
+    Ok((cert, key_pair))
+}
+
+fn self_signed_params(hostname: &str) -> CertificateParams {
+    let mut params = CertificateParams::default();
+
+    let mut distinguished_name = DistinguishedName::new();
+    distinguished_name.push(DnType::CommonName, hostname);
+    distinguished_name.push(DnType::OrganizationName, "grafton-server development");
+    params.distinguished_name = distinguished_name;
+
+    for name in [hostname, "localhost", "127.0.0.1", "::1"] {
+        let san = match name.parse::<IpAddr>() {
+            Ok(ip) => SanType::IpAddress(ip),
+            Err(_) => match name.to_string().try_into() {
+                Ok(dns) => SanType::DnsName(dns),
+                Err(e) => {
+                    warn!("Skipping invalid host name {} in certificate: {}", name, e);
+                    continue;
+                }
+            },
+        };
+
+        if !params.subject_alt_names.contains(&san) {
+            params.subject_alt_names.push(san);
+        }
+    }
+
+    let now = OffsetDateTime::now_utc();
+    params.not_before = now - Duration::days(1);
+    params.not_after = now + Duration::days(VALIDITY_DAYS);
+
+    params
+}
+
+fn write_file(path: &Path, contents: &[u8], mode: u32) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, mode);
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    io::Write::write_all(&mut options.open(path)?, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer};
+
+    use super::*;
+
+    #[test]
+    fn generated_certificate_covers_hostname_and_loopback() {
+        let dir = std::env::temp_dir().join(format!("grafton-self-signed-{}", std::process::id()));
+        let ssl_config = SslConfig {
+            generate_self_signed: true,
+            cert_path: dir.join("tls/cert.pem").to_string_lossy().into_owned(),
+            key_path: dir.join("tls/key.pem").to_string_lossy().into_owned(),
+            ..Default::default()
+        };
+
+        ensure_self_signed(&ssl_config, "dev.example").unwrap();
+        let first = fs::read(&ssl_config.cert_path).unwrap();
+
+        let der = rustls_pemfile::certs(&mut &first[..])
+            .next()
+            .unwrap()
+            .unwrap();
+        let (_, cert) = X509Certificate::from_der(&der).unwrap();
+        let names = cert
+            .subject_alternative_name()
+            .unwrap()
+            .unwrap()
+            .value
+            .general_names
+            .clone();
+        assert!(names.contains(&GeneralName::DNSName("dev.example")));
+        assert!(names.contains(&GeneralName::DNSName("localhost")));
+        assert!(names.contains(&GeneralName::IPAddress(&[127, 0, 0, 1])));
+
+        ensure_self_signed(&ssl_config, "other.example").unwrap();
+        assert_eq!(fs::read(&ssl_config.cert_path).unwrap(), first);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+};
+
+/// Counters shared by every listener of a running server, available from
+/// [`ServerHandle::stats`](crate::ServerHandle::stats).
+#[derive(Debug, Default)]
+pub struct ServerStats {
+    accept_errors: AtomicU64,
+    tls_handshake_timeouts: AtomicU64,
+    header_read_timeouts: AtomicU64,
+    idle_timeouts: AtomicU64,
+    lifetime_expirations: AtomicU64,
+    connection_limit_rejections: AtomicU64,
+    open_connections: AtomicUsize,
+    open_connections_per_ip: Mutex<HashMap<IpAddr, usize>>,
+}
+
+impl ServerStats {
+    /// Errors returned while accepting connections, of any kind.
+    #[must_use]
+    pub fn accept_errors(&self) -> u64 {
+        self.accept_errors.load(Ordering::Relaxed)
+    }
+
+    /// Connections closed because the TLS handshake did not complete in time.
+    #[must_use]
+    pub fn tls_handshake_timeouts(&self) -> u64 {
+        self.tls_handshake_timeouts.load(Ordering::Relaxed)
+    }
+
+    /// HTTP/1 connections closed because a request head did not arrive in time.
+    #[must_use]
+    pub fn header_read_timeouts(&self) -> u64 {
+        self.header_read_timeouts.load(Ordering::Relaxed)
+    }
+
+    /// Connections closed after sitting idle.
+    #[must_use]
+    pub fn idle_timeouts(&self) -> u64 {
+        self.idle_timeouts.load(Ordering::Relaxed)
+    }
+
+    /// Connections asked to close on reaching their maximum lifetime.
+    #[must_use]
+    pub fn lifetime_expirations(&self) -> u64 {
+        self.lifetime_expirations.load(Ordering::Relaxed)
+    }
+
+    /// Connections closed because a connection limit was reached.
+    #[must_use]
+    pub fn connection_limit_rejections(&self) -> u64 {
+        self.connection_limit_rejections.load(Ordering::Relaxed)
+    }
+
+    /// Connections currently open across every listener.
+    #[must_use]
+    pub fn open_connections(&self) -> usize {
+        self.open_connections.load(Ordering::Relaxed)
+    }
+
+    /// Connections currently open from `ip`, as reported by the PROXY protocol header when
+    /// the listener expects one.
+    #[must_use]
+    pub fn open_connections_from(&self, ip: IpAddr) -> usize {
+        self.per_ip().get(&ip.to_canonical()).copied().unwrap_or(0)
+    }
+
+    fn per_ip(&self) -> MutexGuard<'_, HashMap<IpAddr, usize>> {
+        self.open_connections_per_ip
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn open_connection(&self) {
+        self.open_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn close_connection(&self) {
+        self.open_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Count a connection from `ip` as open unless `max` are open from it already.
+    pub fn try_open_connection_from(&self, ip: IpAddr, max: Option<usize>) -> bool {
+        let ip = ip.to_canonical();
+        let mut per_ip = self.per_ip();
+        let open = per_ip.get(&ip).copied().unwrap_or(0);
+        if max.is_some_and(|max| open >= max) {
+            return false;
+        }
+        per_ip.insert(ip, open + 1);
+        true
+    }
+
+    pub fn close_connection_from(&self, ip: IpAddr) {
+        let ip = ip.to_canonical();
+        let mut per_ip = self.per_ip();
+        if let Some(open) = per_ip.get_mut(&ip) {
+            *open -= 1;
+            if *open == 0 {
+                per_ip.remove(&ip);
+            }
+        }
+    }
+
+    pub fn record_connection_limit_rejection(&self) {
+        self.connection_limit_rejections
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_accept_error(&self) {
+        self.accept_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_tls_handshake_timeout(&self) {
+        self.tls_handshake_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_header_read_timeout(&self) {
+        self.header_read_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_idle_timeout(&self) {
+        self.idle_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_lifetime_expiration(&self) {
+        self.lifetime_expirations.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,79 @@
+#![cfg(target_os = "linux")]
+
+use std::time::Duration;
+
+use {
+    grafton_server::{axum::routing::get, Builder, Config, GraftonRouter, ListenerConfig},
+    tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    },
+};
+
+fn set_open_files_limit(limit: u64) -> u64 {
+    let mut current = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: both calls only read or write the `rlimit` passed to them.
+    unsafe {
+        assert_eq!(libc::getrlimit(libc::RLIMIT_NOFILE, &mut current), 0);
+        let updated = libc::rlimit {
+            rlim_cur: limit,
+            ..current
+        };
+        assert_eq!(libc::setrlimit(libc::RLIMIT_NOFILE, &updated), 0);
+    }
+    current.rlim_cur
+}
+
+fn open_files() -> u64 {
+    std::fs::read_dir("/proc/self/fd").unwrap().count() as u64
+}
+
+async fn request(addr: std::net::SocketAddr) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test]
+async fn test_listener_survives_file_descriptor_exhaustion() {
+    let mut config = Config::default();
+    config.website.shutdown_grace_period_secs = 1;
+    config.website.listeners = vec![ListenerConfig {
+        port: 0,
+        ..Default::default()
+    }];
+
+    let server = Builder::new(config)
+        .with_router(|_| GraftonRouter::new().route("/", get(|| async { "ok" })))
+        .build()
+        .unwrap();
+    let handle = server.start().unwrap();
+    let addr = handle.listeners()[0].local_addr.socket_addr().unwrap();
+    assert!(request(addr).await.ends_with("ok"));
+
+    // Leave room for a few client sockets but not for the server to accept them all.
+    let original = set_open_files_limit(open_files() + 8);
+    let mut clients = Vec::new();
+    while let Ok(stream) = TcpStream::connect(addr).await {
+        clients.push(stream);
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(handle.stats().accept_errors() > 0);
+
+    drop(clients);
+    set_open_files_limit(original);
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    assert!(request(addr).await.ends_with("ok"));
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+}
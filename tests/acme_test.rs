@@ -0,0 +1,152 @@
+//! ACME tests against a local [pebble](https://github.com/letsencrypt/pebble) server.
+//!
+//! Start pebble with `PEBBLE_VA_ALWAYS_VALID=1` so challenges need not be reachable, then
+//! run `PEBBLE_CA=path/to/pebble.minica.pem cargo test --test acme_test -- --ignored`.
+//! `PEBBLE_DIRECTORY` overrides the default `https://localhost:14000/dir`.
+
+use std::{env, time::Duration};
+
+use {
+    grafton_server::{AcmeConfig, Builder, Config, GraftonRouter, ListenerConfig},
+    rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair},
+    x509_parser::{certificate::X509Certificate, prelude::FromDer},
+};
+
+fn acme_config(name: &str) -> Config {
+    let mut config = Config::default();
+    config.website.shutdown_grace_period_secs = 1;
+    config.website.listeners = vec![
+        ListenerConfig {
+            port: 0,
+            tls: true,
+            ..Default::default()
+        },
+        ListenerConfig {
+            port: 0,
+            ..Default::default()
+        },
+    ];
+    config.website.acme = AcmeConfig {
+        enabled: true,
+        domains: vec!["grafton.test".into()],
+        cache_dir: env::temp_dir().join(format!("grafton-acme-{name}-{}", std::process::id())),
+        ..Default::default()
+    };
+    config
+}
+
+#[tokio::test]
+async fn test_starts_with_temporary_certificate_before_issuance() {
+    let config = acme_config("temporary");
+    let cache_dir = config.website.acme.cache_dir.clone();
+
+    let server = Builder::new(config)
+        .with_router(|_| GraftonRouter::new())
+        .build()
+        .unwrap();
+    let handle = server.start().unwrap();
+
+    assert_eq!(handle.listeners().len(), 2);
+    assert!(!cache_dir.join("certificate.pem").exists());
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+}
+
+/// Start a server with `chain` and `key` cached as its ACME certificate, checking that it
+/// serves a temporary certificate instead of failing.
+async fn start_with_cached(name: &str, chain: &str, key: &KeyPair) {
+    let config = acme_config(name);
+    let cache_dir = config.website.acme.cache_dir.clone();
+    std::fs::create_dir_all(&cache_dir).unwrap();
+    std::fs::write(config.website.acme.cert_path(), chain).unwrap();
+    std::fs::write(config.website.acme.key_path(), key.serialize_pem()).unwrap();
+
+    let server = Builder::new(config)
+        .with_router(|_| GraftonRouter::new())
+        .build()
+        .unwrap();
+    let handle = server.start().unwrap();
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+    std::fs::remove_dir_all(cache_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_starts_with_expired_cached_certificate() {
+    let key = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(vec!["grafton.test".to_string()]).unwrap();
+    params.not_before = time::OffsetDateTime::now_utc() - time::Duration::days(90);
+    params.not_after = time::OffsetDateTime::now_utc() - time::Duration::days(1);
+    let cert = params.self_signed(&key).unwrap();
+
+    start_with_cached("expired", &cert.pem(), &key).await;
+}
+
+#[tokio::test]
+async fn test_starts_with_misordered_cached_chain() {
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, "grafton test CA");
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+    let key = KeyPair::generate().unwrap();
+    let leaf = CertificateParams::new(vec!["grafton.test".to_string()])
+        .unwrap()
+        .signed_by(&key, &ca, &ca_key)
+        .unwrap();
+
+    start_with_cached("misordered", &(ca.pem() + &leaf.pem()), &key).await;
+}
+
+#[tokio::test]
+async fn test_starts_with_mismatched_cached_key() {
+    let cert = CertificateParams::new(vec!["grafton.test".to_string()])
+        .unwrap()
+        .self_signed(&KeyPair::generate().unwrap())
+        .unwrap();
+
+    start_with_cached("mismatched", &cert.pem(), &KeyPair::generate().unwrap()).await;
+}
+
+#[tokio::test]
+#[ignore = "requires a pebble ACME server"]
+async fn test_certificate_is_issued_by_pebble() {
+    let mut config = acme_config("pebble");
+    let acme = &mut config.website.acme;
+    acme.directory_url =
+        env::var("PEBBLE_DIRECTORY").unwrap_or_else(|_| "https://localhost:14000/dir".into());
+    acme.ca_roots_path = Some(env::var("PEBBLE_CA").expect("PEBBLE_CA must be set"));
+    acme.terms_of_service_agreed = true;
+    acme.contact = vec!["mailto:admin@grafton.test".into()];
+    let cert_path = acme.cert_path();
+
+    let server = Builder::new(config)
+        .with_router(|_| GraftonRouter::new())
+        .build()
+        .unwrap();
+    let handle = server.start().unwrap();
+
+    tokio::time::timeout(Duration::from_secs(60), async {
+        while !cert_path.exists() {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    })
+    .await
+    .expect("certificate was not issued in time");
+
+    let pem = std::fs::read(&cert_path).unwrap();
+    let der = rustls_pemfile::certs(&mut &pem[..])
+        .next()
+        .unwrap()
+        .unwrap();
+    let (_, cert) = X509Certificate::from_der(&der).unwrap();
+    assert_ne!(cert.subject(), cert.issuer());
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+    std::fs::remove_dir_all(cert_path.parent().unwrap()).unwrap();
+}
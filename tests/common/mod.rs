@@ -0,0 +1,28 @@
+use std::net::SocketAddr;
+
+use grafton_server::{Builder, Config, GraftonRouter, ListenerConfig, ServerHandle};
+
+/// Start serving `router`, on a single TCP listener with an ephemeral port unless `config`
+/// has listeners of its own, returning the handle and the first listener's address.
+pub fn start(mut config: Config, router: GraftonRouter<Config>) -> (ServerHandle, SocketAddr) {
+    config.website.shutdown_grace_period_secs = 1;
+    if config.website.listeners.is_empty() {
+        config.website.listeners = vec![ListenerConfig {
+            port: 0,
+            ..Default::default()
+        }];
+    }
+
+    let server = Builder::new(config)
+        .with_router(|_| router)
+        .build()
+        .unwrap();
+    let handle = server.start().unwrap();
+    let addr = handle.listeners()[0].local_addr.socket_addr().unwrap();
+    (handle, addr)
+}
+
+pub async fn stop(handle: ServerHandle) {
+    handle.shutdown();
+    handle.wait().await.unwrap();
+}
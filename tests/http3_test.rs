@@ -0,0 +1,121 @@
+#![cfg(feature = "http3")]
+
+use std::{net::SocketAddr, path::Path, sync::Arc};
+
+use {
+    bytes::Buf,
+    grafton_server::{axum::routing::get, Builder, Config, GraftonRouter, ListenerConfig},
+    quinn::crypto::rustls::QuicClientConfig,
+    rustls_pki_types::ServerName,
+    tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    },
+    tokio_rustls::{
+        rustls::{ClientConfig, RootCertStore},
+        TlsConnector,
+    },
+};
+
+fn http3_config(dir: &Path) -> Config {
+    let mut config = Config::default();
+    config.website.shutdown_grace_period_secs = 1;
+    config.website.listeners = vec![ListenerConfig {
+        port: 0,
+        tls: true,
+        http3: true,
+        ..Default::default()
+    }];
+
+    let ssl = &mut config.website.bind_ssl_config;
+    ssl.enabled = true;
+    ssl.generate_self_signed = true;
+    ssl.cert_path = dir.join("cert.pem").to_string_lossy().into();
+    ssl.key_path = dir.join("key.pem").to_string_lossy().into();
+    config
+}
+
+fn client_config(dir: &Path, alpn: &[u8]) -> ClientConfig {
+    let pem = std::fs::read(dir.join("cert.pem")).unwrap();
+    let mut roots = RootCertStore::empty();
+    for cert in rustls_pemfile::certs(&mut &pem[..]) {
+        roots.add(cert.unwrap()).unwrap();
+    }
+
+    let mut config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    config.alpn_protocols = vec![alpn.to_vec()];
+    config
+}
+
+async fn get_over_tcp(dir: &Path, addr: SocketAddr) -> String {
+    let connector = TlsConnector::from(Arc::new(client_config(dir, b"http/1.1")));
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let mut stream = connector
+        .connect(ServerName::try_from("localhost").unwrap(), stream)
+        .await
+        .unwrap();
+
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+async fn get_over_http3(dir: &Path, addr: SocketAddr) -> (u16, String) {
+    let crypto = QuicClientConfig::try_from(client_config(dir, b"h3")).unwrap();
+    let mut endpoint = quinn::Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+
+    let connection = endpoint.connect(addr, "localhost").unwrap().await.unwrap();
+    let (mut driver, mut send_request) = h3::client::new(h3_quinn::Connection::new(connection))
+        .await
+        .unwrap();
+    let driver =
+        tokio::spawn(async move { std::future::poll_fn(|cx| driver.poll_close(cx)).await });
+
+    let request =
+        grafton_server::axum::http::Request::get(format!("https://localhost:{}/", addr.port()))
+            .body(())
+            .unwrap();
+    let mut stream = send_request.send_request(request).await.unwrap();
+    stream.finish().await.unwrap();
+
+    let response = stream.recv_response().await.unwrap();
+    let mut body = Vec::new();
+    while let Some(mut chunk) = stream.recv_data().await.unwrap() {
+        body.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+    }
+
+    drop(send_request);
+    driver.abort();
+    endpoint.close(0u32.into(), b"done");
+    (response.status().as_u16(), String::from_utf8(body).unwrap())
+}
+
+#[tokio::test]
+async fn test_http3_is_served_and_advertised() {
+    let dir = std::env::temp_dir().join(format!("grafton-http3-{}", std::process::id()));
+    let server = Builder::new(http3_config(&dir))
+        .with_router(|_| GraftonRouter::new().route("/", get(|| async { "hello" })))
+        .build()
+        .unwrap();
+    let handle = server.start().unwrap();
+    let addr = handle.listeners()[0].local_addr.socket_addr().unwrap();
+
+    let response = get_over_tcp(&dir, addr).await;
+    assert!(response.starts_with("HTTP/1.1 200"));
+    assert!(response
+        .to_ascii_lowercase()
+        .contains(&format!("alt-svc: h3=\":{}\"; ma=86400", addr.port())));
+
+    assert_eq!(get_over_http3(&dir, addr).await, (200, "hello".to_string()));
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,152 @@
+mod common;
+
+use std::{net::SocketAddr, time::Duration};
+
+use {
+    grafton_server::{
+        axum::routing::get, Config, ConnectionLimitAction, ConnectionLimitConfig, GraftonRouter,
+        ListenerConfig, ServerHandle,
+    },
+    tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    },
+};
+
+use common::stop;
+
+const REQUEST: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+fn start(limits: ConnectionLimitConfig) -> (ServerHandle, SocketAddr) {
+    let mut config = Config::default();
+    config.website.connection_limits = limits;
+    common::start(
+        config,
+        GraftonRouter::new().route("/", get(|| async { "ok" })),
+    )
+}
+
+/// Open a connection and wait until the server counts it.
+async fn hold_connection(handle: &ServerHandle, addr: SocketAddr) -> TcpStream {
+    let open = handle.stats().open_connections_from(addr.ip());
+    let stream = TcpStream::connect(addr).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while handle.stats().open_connections_from(addr.ip()) == open {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    stream
+}
+
+async fn read_response(stream: &mut TcpStream) -> String {
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .await
+        .unwrap_or_default();
+    response
+}
+
+#[tokio::test]
+async fn test_connections_over_the_limit_are_closed() {
+    let (handle, addr) = start(ConnectionLimitConfig {
+        max_connections: Some(1),
+        on_limit: ConnectionLimitAction::Close,
+        ..Default::default()
+    });
+
+    let held = hold_connection(&handle, addr).await;
+    let mut rejected = TcpStream::connect(addr).await.unwrap();
+    rejected.write_all(REQUEST).await.ok();
+    assert_eq!(read_response(&mut rejected).await, "");
+    assert_eq!(handle.stats().connection_limit_rejections(), 1);
+    assert_eq!(handle.stats().open_connections(), 1);
+
+    drop(held);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(REQUEST).await.unwrap();
+    assert!(read_response(&mut stream).await.ends_with("ok"));
+
+    stop(handle).await;
+}
+
+#[tokio::test]
+async fn test_listener_pauses_at_the_limit() {
+    let (handle, addr) = start(ConnectionLimitConfig {
+        max_connections: Some(1),
+        on_limit: ConnectionLimitAction::Pause,
+        ..Default::default()
+    });
+
+    let held = hold_connection(&handle, addr).await;
+    let mut queued = TcpStream::connect(addr).await.unwrap();
+    queued.write_all(REQUEST).await.unwrap();
+    let waiting = tokio::time::timeout(Duration::from_millis(300), read_response(&mut queued));
+    assert!(
+        waiting.await.is_err(),
+        "queued connection must not be served"
+    );
+    assert_eq!(handle.stats().open_connections(), 1);
+
+    drop(held);
+    assert!(read_response(&mut queued).await.ends_with("ok"));
+    assert_eq!(handle.stats().connection_limit_rejections(), 0);
+
+    stop(handle).await;
+}
+
+#[tokio::test]
+async fn test_paused_listeners_share_the_limit() {
+    let mut config = Config::default();
+    config.website.connection_limits = ConnectionLimitConfig {
+        max_connections: Some(1),
+        on_limit: ConnectionLimitAction::Pause,
+        ..Default::default()
+    };
+    let listener = ListenerConfig {
+        port: 0,
+        ..Default::default()
+    };
+    config.website.listeners = vec![listener.clone(), listener];
+    let (handle, _) = common::start(
+        config,
+        GraftonRouter::new().route("/", get(|| async { "ok" })),
+    );
+
+    for listener in handle.listeners().iter().flat_map(|l| [l, l]) {
+        let addr = listener.local_addr.socket_addr().unwrap();
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(REQUEST).await.unwrap();
+        let response = tokio::time::timeout(Duration::from_secs(5), read_response(&mut stream));
+        assert!(
+            response.await.unwrap().ends_with("ok"),
+            "listener {addr} must accept"
+        );
+    }
+
+    stop(handle).await;
+}
+
+#[tokio::test]
+async fn test_per_ip_limit_closes_extra_connections() {
+    let (handle, addr) = start(ConnectionLimitConfig {
+        max_connections_per_ip: Some(1),
+        ..Default::default()
+    });
+
+    let _held = hold_connection(&handle, addr).await;
+    let mut rejected = TcpStream::connect(addr).await.unwrap();
+    rejected.write_all(REQUEST).await.ok();
+    assert_eq!(read_response(&mut rejected).await, "");
+    assert_eq!(handle.stats().connection_limit_rejections(), 1);
+    assert_eq!(
+        handle.stats().open_connections_from(addr.ip()),
+        1,
+        "only the held connection is counted"
+    );
+
+    stop(handle).await;
+}
@@ -0,0 +1,175 @@
+use std::{path::PathBuf, sync::Arc};
+
+use {
+    grafton_server::{
+        axum::routing::get, Builder, ClientAuth, ClientCertificate, Config, GraftonRouter,
+        ListenerConfig,
+    },
+    rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa, KeyPair},
+    rustls_pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer, ServerName},
+    tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    },
+    tokio_rustls::{
+        rustls::{ClientConfig, RootCertStore},
+        TlsConnector,
+    },
+};
+
+struct Pki {
+    dir: PathBuf,
+    ca: Certificate,
+    ca_key: KeyPair,
+}
+
+impl Pki {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("grafton-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_key = KeyPair::generate().unwrap();
+        let ca = params.self_signed(&ca_key).unwrap();
+        std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+
+        Self { dir, ca, ca_key }
+    }
+
+    fn issue(&self, name: &str) -> (Certificate, KeyPair) {
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec![name.to_string()])
+            .unwrap()
+            .signed_by(&key, &self.ca, &self.ca_key)
+            .unwrap();
+        (cert, key)
+    }
+
+    fn config(&self, client_auth: ClientAuth) -> Config {
+        let (cert, key) = self.issue("localhost");
+        std::fs::write(self.dir.join("cert.pem"), cert.pem()).unwrap();
+        std::fs::write(self.dir.join("key.pem"), key.serialize_pem()).unwrap();
+
+        let mut config = Config::default();
+        config.website.shutdown_grace_period_secs = 1;
+        config.website.listeners = vec![ListenerConfig {
+            port: 0,
+            tls: true,
+            ..Default::default()
+        }];
+
+        let ssl = &mut config.website.bind_ssl_config;
+        ssl.enabled = true;
+        ssl.cert_path = self.dir.join("cert.pem").to_string_lossy().into();
+        ssl.key_path = self.dir.join("key.pem").to_string_lossy().into();
+        ssl.client_auth = client_auth;
+        ssl.client_ca_path = Some(self.dir.join("ca.pem").to_string_lossy().into());
+        config
+    }
+
+    fn connector(&self, client_cert: Option<(Certificate, KeyPair)>) -> TlsConnector {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.ca.der().clone()).unwrap();
+        let builder = ClientConfig::builder().with_root_certificates(roots);
+
+        let config = match client_cert {
+            Some((cert, key)) => builder
+                .with_client_auth_cert(
+                    vec![cert.der().clone()],
+                    PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der())),
+                )
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        };
+
+        TlsConnector::from(Arc::new(config))
+    }
+}
+
+impl Drop for Pki {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+async fn get_root(
+    connector: &TlsConnector,
+    port: u16,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    let mut stream = connector
+        .connect(ServerName::try_from("localhost")?, stream)
+        .await?;
+
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    Ok(response)
+}
+
+fn router() -> GraftonRouter<Config> {
+    GraftonRouter::new().route(
+        "/",
+        get(|cert: Option<ClientCertificate>| async move {
+            cert.map_or_else(
+                || "anonymous".to_string(),
+                |cert| cert.subject_alt_names.join(","),
+            )
+        }),
+    )
+}
+
+#[tokio::test]
+async fn test_required_client_auth_rejects_anonymous_clients() {
+    let pki = Pki::new("mtls-required");
+    let server = Builder::new(pki.config(ClientAuth::Required))
+        .with_router(|_| router())
+        .build()
+        .unwrap();
+    let handle = server.start().unwrap();
+    let port = handle.listeners()[0]
+        .local_addr
+        .socket_addr()
+        .unwrap()
+        .port();
+
+    let response = get_root(&pki.connector(Some(pki.issue("client.internal"))), port)
+        .await
+        .unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"));
+    assert!(response.ends_with("DNS:client.internal"));
+
+    assert!(get_root(&pki.connector(None), port).await.is_err());
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_optional_client_auth_allows_anonymous_clients() {
+    let pki = Pki::new("mtls-optional");
+    let server = Builder::new(pki.config(ClientAuth::Optional))
+        .with_router(|_| router())
+        .build()
+        .unwrap();
+    let handle = server.start().unwrap();
+    let port = handle.listeners()[0]
+        .local_addr
+        .socket_addr()
+        .unwrap()
+        .port();
+
+    let response = get_root(&pki.connector(None), port).await.unwrap();
+    assert!(response.ends_with("anonymous"));
+
+    let response = get_root(&pki.connector(Some(pki.issue("client.internal"))), port)
+        .await
+        .unwrap();
+    assert!(response.ends_with("DNS:client.internal"));
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+}
@@ -0,0 +1,419 @@
+use std::{net::TcpListener, time::Duration};
+
+use grafton_server::{axum::routing::get, Builder, Config, Error, GraftonRouter, ListenerConfig};
+
+fn test_config() -> Config {
+    let mut config = Config::default();
+    config.website.bind_ports.http = 0;
+    config.website.shutdown_grace_period_secs = 1;
+    config
+}
+
+#[tokio::test]
+async fn test_shutdown_completes() {
+    let server = Builder::new(test_config())
+        .with_router(|_| GraftonRouter::new().route("/", get(|| async { "ok" })))
+        .build()
+        .unwrap();
+
+    let handle = server.start().unwrap();
+    assert!(!handle.is_shutting_down());
+
+    handle.shutdown();
+    assert!(handle.is_shutting_down());
+
+    tokio::time::timeout(Duration::from_secs(5), handle.wait())
+        .await
+        .expect("server did not shut down in time")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_shutdown_token_triggers_shutdown() {
+    let server = Builder::new(test_config())
+        .with_router(|_| GraftonRouter::new())
+        .build()
+        .unwrap();
+
+    let handle = server.start().unwrap();
+    handle.shutdown_token().cancel();
+
+    tokio::time::timeout(Duration::from_secs(5), handle.wait())
+        .await
+        .expect("server did not shut down in time")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_start_fails_when_port_in_use() {
+    let occupied = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut config = test_config();
+    config.website.bind_ports.http = occupied.local_addr().unwrap().port();
+
+    let server = Builder::new(config)
+        .with_router(|_| GraftonRouter::new())
+        .build()
+        .unwrap();
+
+    let result = server.start();
+    assert!(matches!(result, Err(Error::BindError { .. })));
+}
+
+#[tokio::test]
+async fn test_start_fails_with_missing_certificate() {
+    let mut config = test_config();
+    config.website.bind_ports.https = 0;
+    config.website.bind_ssl_config.enabled = true;
+    config.website.bind_ssl_config.cert_path = "does/not/exist/cert.pem".into();
+
+    let server = Builder::new(config)
+        .with_router(|_| GraftonRouter::new())
+        .build()
+        .unwrap();
+
+    let result = server.start();
+    assert!(matches!(result, Err(Error::TlsFileError { .. })));
+}
+
+#[tokio::test]
+async fn test_start_binds_every_listener() {
+    let mut config = test_config();
+    config.website.listeners = vec![
+        ListenerConfig {
+            name: Some("first".into()),
+            port: 0,
+            ..Default::default()
+        },
+        ListenerConfig {
+            name: Some("second".into()),
+            port: 0,
+            ..Default::default()
+        },
+    ];
+
+    let server = Builder::new(config)
+        .with_router(|_| GraftonRouter::new())
+        .build()
+        .unwrap();
+
+    let handle = server.start().unwrap();
+    let listeners = handle.listeners();
+
+    assert_eq!(listeners.len(), 2);
+    assert_eq!(listeners[0].name.as_deref(), Some("first"));
+    assert_eq!(listeners[1].name.as_deref(), Some("second"));
+    assert!(listeners
+        .iter()
+        .all(|l| l.local_addr.socket_addr().is_some_and(|a| a.port() != 0) && !l.tls));
+    assert_ne!(listeners[0].local_addr, listeners[1].local_addr);
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_ephemeral_ports_are_reported_in_context() {
+    use std::sync::Arc;
+
+    use grafton_server::{axum::extract::State, model::Context};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    let mut config = test_config();
+    config.website.public_ports.http = 0;
+
+    let server = Builder::new(config)
+        .with_router(|_| {
+            GraftonRouter::new().route(
+                "/",
+                get(|State(context): State<Arc<Context<Config>>>| async move {
+                    format!(
+                        "{} {}",
+                        context.listeners()[0].local_addr,
+                        context.website().public_server_url()
+                    )
+                }),
+            )
+        })
+        .build()
+        .unwrap();
+    let handle = server.start().unwrap();
+    let addr = handle.listeners()[0].local_addr.socket_addr().unwrap();
+    assert_ne!(addr.port(), 0);
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(
+        response.ends_with(&format!("{addr} http://localhost:{}", addr.port())),
+        "{response}"
+    );
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_listener_protocol_selection() {
+    use grafton_server::{axum::http::Version, HttpProtocol};
+    use http_body_util::Empty;
+    use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+
+    let mut config = test_config();
+    config.website.listeners = vec![
+        ListenerConfig {
+            port: 0,
+            protocol: HttpProtocol::Http1,
+            ..Default::default()
+        },
+        ListenerConfig {
+            port: 0,
+            protocol: HttpProtocol::Http2,
+            ..Default::default()
+        },
+    ];
+
+    let server = Builder::new(config)
+        .with_router(|_| GraftonRouter::new().route("/", get(|| async { "ok" })))
+        .build()
+        .unwrap();
+    let handle = server.start().unwrap();
+    let listeners = handle.listeners();
+    let url = |index: usize| {
+        let addr = listeners[index].local_addr.socket_addr().unwrap();
+        format!("http://{addr}/").parse::<hyper::Uri>().unwrap()
+    };
+
+    let http1 = Client::builder(TokioExecutor::new()).build_http::<Empty<bytes::Bytes>>();
+    let h2c = Client::builder(TokioExecutor::new())
+        .http2_only(true)
+        .build_http::<Empty<bytes::Bytes>>();
+
+    let response = http1.get(url(0)).await.unwrap();
+    assert_eq!(response.version(), Version::HTTP_11);
+    assert!(h2c.get(url(0)).await.is_err());
+
+    let response = h2c.get(url(1)).await.unwrap();
+    assert_eq!(response.version(), Version::HTTP_2);
+    assert!(http1.get(url(1)).await.is_err());
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_client_ip_follows_trusted_proxies() {
+    use std::net::SocketAddr;
+
+    use grafton_server::{axum::extract::ConnectInfo, ClientIp};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    let mut config = test_config();
+    config.website.trusted_proxies = vec!["127.0.0.0/8".parse().unwrap()];
+
+    let server = Builder::new(config)
+        .with_router(|_| {
+            GraftonRouter::new().route(
+                "/",
+                get(
+                    |ConnectInfo(peer): ConnectInfo<SocketAddr>,
+                     ClientIp(client): ClientIp| async move {
+                        format!("{} {client}", peer.ip())
+                    },
+                ),
+            )
+        })
+        .build()
+        .unwrap();
+    let handle = server.start().unwrap();
+    let addr = handle.listeners()[0].local_addr.socket_addr().unwrap();
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nX-Forwarded-For: 198.51.100.7, 127.0.0.2\r\n\
+              Connection: close\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.ends_with("127.0.0.1 198.51.100.7"));
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_proxy_protocol_reports_client_address() {
+    use grafton_server::{axum::extract::ConnectInfo, PeerAddr, ProxyHeader};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    let mut config = test_config();
+    config.website.listeners = vec![
+        ListenerConfig {
+            port: 0,
+            proxy_protocol: true,
+            proxy_protocol_trusted: vec!["127.0.0.0/8".parse().unwrap()],
+            ..Default::default()
+        },
+        ListenerConfig {
+            port: 0,
+            proxy_protocol: true,
+            proxy_protocol_trusted: vec!["10.0.0.0/8".parse().unwrap()],
+            ..Default::default()
+        },
+    ];
+
+    let server = Builder::new(config)
+        .with_router(|_| {
+            GraftonRouter::new().route(
+                "/",
+                get(
+                    |ConnectInfo(peer): ConnectInfo<PeerAddr>,
+                     ConnectInfo(header): ConnectInfo<ProxyHeader>| async move {
+                        format!("{peer} {}", header.destination)
+                    },
+                ),
+            )
+        })
+        .build()
+        .unwrap();
+    let handle = server.start().unwrap();
+    let listeners = handle.listeners();
+
+    let request = |index: usize| {
+        let addr = listeners[index].local_addr.socket_addr().unwrap();
+        async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream
+                .write_all(
+                    b"PROXY TCP4 192.0.2.1 198.51.100.2 51000 443\r\n\
+                      GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                )
+                .await
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.map(|_| response)
+        }
+    };
+
+    let response = request(0).await.unwrap();
+    assert!(response.ends_with("192.0.2.1:51000 198.51.100.2:443"));
+
+    // Connections from outside the trusted networks are closed without a response.
+    assert!(request(1)
+        .await
+        .map_or(true, |response| response.is_empty()));
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_reuse_port_listener_serves_every_connection() {
+    use grafton_server::TcpSocketConfig;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    let mut config = test_config();
+    config.website.listeners = vec![ListenerConfig {
+        port: 0,
+        reuse_port: true,
+        acceptors: Some(4),
+        tcp: TcpSocketConfig {
+            keepalive_secs: Some(30),
+            recv_buffer_size: Some(64 * 1024),
+            ..Default::default()
+        },
+        ..Default::default()
+    }];
+
+    let server = Builder::new(config)
+        .with_router(|_| GraftonRouter::new().route("/", get(|| async { "ok" })))
+        .build()
+        .unwrap();
+    let handle = server.start().unwrap();
+    assert_eq!(handle.listeners().len(), 1);
+    let addr = handle.listeners()[0].local_addr.socket_addr().unwrap();
+
+    for _ in 0..32 {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.ends_with("ok"));
+    }
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_unix_listener_serves_router_with_peer_identity() {
+    use grafton_server::{axum::extract::ConnectInfo, ListenerKind, PeerAddr};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::UnixStream,
+    };
+
+    let path = std::env::temp_dir().join(format!("grafton-test-{}.sock", std::process::id()));
+    // A socket file left behind by a previous process is cleaned up before binding.
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+    let mut config = test_config();
+    config.website.listeners = vec![ListenerConfig {
+        kind: ListenerKind::Unix,
+        path: Some(path.clone()),
+        socket_mode: Some(0o600),
+        ..Default::default()
+    }];
+
+    let server = Builder::new(config)
+        .with_router(|_| {
+            GraftonRouter::new().route(
+                "/",
+                get(|ConnectInfo(peer): ConnectInfo<PeerAddr>| async move {
+                    matches!(peer, PeerAddr::Unix(_)).to_string()
+                }),
+            )
+        })
+        .build()
+        .unwrap();
+
+    let handle = server.start().unwrap();
+
+    let mut stream = UnixStream::connect(&path).await.unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200"));
+    assert!(response.ends_with("true"));
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+    assert!(!path.exists());
+}
@@ -0,0 +1,51 @@
+#![cfg(unix)]
+
+use std::{net::TcpListener as StdTcpListener, os::fd::IntoRawFd};
+
+use grafton_server::{
+    axum::routing::get, Builder, Config, GraftonRouter, InheritFd, ListenerConfig,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+#[tokio::test]
+async fn test_listener_adopts_socket_activated_fd() {
+    let socket = StdTcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    let fd = socket.into_raw_fd();
+
+    std::env::set_var("LISTEN_PID", std::process::id().to_string());
+    std::env::set_var("LISTEN_FDS", "1");
+    std::env::set_var("LISTEN_FDS_FIRST_FD", fd.to_string());
+    std::env::set_var("LISTEN_FDNAMES", "web");
+
+    let mut config = Config::default();
+    config.website.listeners = vec![ListenerConfig {
+        // Never bound: the inherited socket is used instead.
+        port: 1,
+        inherit_fd: Some(InheritFd::Name("web".into())),
+        ..Default::default()
+    }];
+
+    let server = Builder::new(config)
+        .with_router(|_| GraftonRouter::new().route("/", get(|| async { "activated" })))
+        .build()
+        .unwrap();
+
+    let handle = server.start().unwrap();
+    assert_eq!(handle.listeners()[0].local_addr.socket_addr(), Some(addr));
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.ends_with("activated"));
+
+    handle.shutdown();
+    handle.wait().await.unwrap();
+}
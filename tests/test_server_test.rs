@@ -0,0 +1,161 @@
+#![cfg(feature = "test-util")]
+
+use std::{path::Path, sync::Arc};
+
+use {
+    grafton_server::{
+        axum::{
+            extract::State,
+            http::{header::COOKIE, HeaderMap, StatusCode},
+            routing::{get, post},
+            Json,
+        },
+        model::Context,
+        test::{self, TestServer},
+        Builder, Config, GraftonRouter,
+    },
+    serde::{Deserialize, Serialize},
+    serde_json::json,
+};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+struct Greeting {
+    name: String,
+    count: u32,
+}
+
+fn app(_: &Arc<Context<Config>>) -> GraftonRouter<Config> {
+    GraftonRouter::new()
+        .route(
+            "/greet",
+            post(|Json(greeting): Json<Greeting>| async move {
+                Json(Greeting {
+                    name: format!("Hello, {}", greeting.name),
+                    count: greeting.count + 1,
+                })
+            }),
+        )
+        .route(
+            "/login",
+            post(|| async { [("set-cookie", "session=abc123; Path=/; HttpOnly")] }),
+        )
+        .route(
+            "/logout",
+            post(|| async { [("set-cookie", "session=; Path=/; Max-Age=0")] }),
+        )
+        .route(
+            "/whoami",
+            get(|headers: HeaderMap| async move {
+                match headers.get(COOKIE) {
+                    Some(cookie) => (StatusCode::OK, cookie.to_str().unwrap().to_owned()),
+                    None => (StatusCode::UNAUTHORIZED, String::new()),
+                }
+            }),
+        )
+}
+
+#[tokio::test]
+async fn test_json_requests_and_assertions() {
+    let server = TestServer::new(app).unwrap();
+
+    let response = server
+        .post("/greet")
+        .json(&Greeting {
+            name: "Ada".into(),
+            count: 1,
+        })
+        .await;
+    response
+        .assert_ok()
+        .assert_header("content-type", "application/json")
+        .assert_json(&json!({ "count": 2, "name": "Hello, Ada" }));
+    assert_eq!(
+        response.json::<Greeting>(),
+        Greeting {
+            name: "Hello, Ada".into(),
+            count: 2,
+        }
+    );
+
+    server
+        .get("/missing")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_client_keeps_cookies_between_requests() {
+    let server = TestServer::new(app).unwrap();
+
+    server
+        .get("/whoami")
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+
+    let login = server.post("/login").await;
+    assert_eq!(login.cookie("session").as_deref(), Some("abc123"));
+    assert_eq!(server.client().cookie("session").as_deref(), Some("abc123"));
+    server
+        .get("/whoami")
+        .await
+        .assert_ok()
+        .assert_text("session=abc123");
+
+    let other = server.client().fresh();
+    other
+        .get("/whoami")
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+
+    server.post("/logout").await.assert_ok();
+    assert_eq!(server.client().cookie("session"), None);
+    server
+        .get("/whoami")
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_tls_server_is_trusted_and_cleaned_up() {
+    let config = test::tls_config();
+    let cert_path = config.website.bind_ssl_config.cert_path.clone();
+    let server = TestServer::start(
+        Builder::new(config)
+            .with_router(|_| GraftonRouter::new().route("/", get(|| async { "secure" }))),
+    )
+    .unwrap();
+    assert!(server.url("/").starts_with("https://127.0.0.1:"));
+    assert_eq!(server.handle().listeners().len(), 1);
+
+    server.get("/").await.assert_ok().assert_text("secure");
+
+    assert!(Path::new(&cert_path).exists());
+    server.shutdown().await.unwrap();
+    assert!(!Path::new(&cert_path).exists());
+}
+
+#[tokio::test]
+async fn test_start_uses_a_custom_builder() {
+    let mut config = test::config();
+    config.website.public_hostname = "127.0.0.1".into();
+
+    let server = TestServer::start(Builder::new(config).with_router(|_| {
+        GraftonRouter::new().route(
+            "/url",
+            get(|State(context): State<Arc<Context<Config>>>| async move {
+                context.website().public_server_url()
+            }),
+        )
+    }))
+    .unwrap();
+
+    server
+        .get("/url")
+        .await
+        .assert_ok()
+        .assert_text(&format!("http://{}", server.addr()));
+    server.shutdown().await.unwrap();
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use std::{convert::Infallible, time::Duration};
+
+use {
+    grafton_server::{
+        axum::{body::Body, routing::get},
+        Config, GraftonRouter, ListenerConfig,
+    },
+    http_body_util::channel::Channel,
+    tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    },
+};
+
+use common::{start, stop};
+
+const REQUEST: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+fn router() -> GraftonRouter<Config> {
+    GraftonRouter::new()
+        .route("/", get(|| async { "ok" }))
+        .route("/stream", get(|| async { slow_stream() }))
+}
+
+/// A response body which sends `first`, pauses for two seconds, then sends `last`.
+fn slow_stream() -> Body {
+    let (mut sender, body) = Channel::<_, Infallible>::new(1);
+    tokio::spawn(async move {
+        sender.send_data("first".into()).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        sender.send_data("last".into()).await.unwrap();
+    });
+    Body::new(body)
+}
+
+/// Read until the server closes the connection, returning how long that took.
+async fn wait_for_close(stream: &mut TcpStream) -> Duration {
+    let start = tokio::time::Instant::now();
+    let mut buf = [0; 1024];
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while stream.read(&mut buf).await.is_ok_and(|n| n > 0) {}
+    })
+    .await
+    .unwrap();
+    start.elapsed()
+}
+
+#[tokio::test]
+async fn test_stalled_request_head_is_closed() {
+    let mut config = Config::default();
+    config.website.timeouts.header_read_secs = Some(1);
+    let (handle, addr) = start(config, router());
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: loc")
+        .await
+        .unwrap();
+    wait_for_close(&mut stream).await;
+
+    assert_eq!(handle.stats().header_read_timeouts(), 1);
+    stop(handle).await;
+}
+
+#[tokio::test]
+async fn test_idle_connection_is_closed() {
+    let mut config = Config::default();
+    config.website.timeouts.idle_secs = Some(1);
+    let (handle, addr) = start(config, router());
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(REQUEST).await.unwrap();
+    let mut buf = [0; 1024];
+    let n = stream.read(&mut buf).await.unwrap();
+    assert!(buf[..n].ends_with(b"ok"));
+
+    assert!(wait_for_close(&mut stream).await >= Duration::from_millis(900));
+    assert_eq!(handle.stats().idle_timeouts(), 1);
+    stop(handle).await;
+}
+
+#[tokio::test]
+async fn test_streaming_response_outlives_idle_timeout() {
+    let mut config = Config::default();
+    config.website.timeouts.idle_secs = Some(1);
+    let (handle, addr) = start(config, router());
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /stream HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    let mut buf = [0; 1024];
+    while !response.ends_with(b"0\r\n\r\n") {
+        let n = stream.read(&mut buf).await.unwrap();
+        assert!(n > 0, "connection closed mid-response");
+        response.extend_from_slice(&buf[..n]);
+    }
+
+    let response = String::from_utf8(response).unwrap();
+    assert!(response.contains("first") && response.contains("last"));
+    assert_eq!(handle.stats().idle_timeouts(), 0);
+    stop(handle).await;
+}
+
+#[tokio::test]
+async fn test_busy_connection_closes_at_max_lifetime() {
+    let mut config = Config::default();
+    config.website.timeouts.max_connection_lifetime_secs = Some(1);
+    let (handle, addr) = start(config, router());
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let mut buf = [0; 1024];
+    let start = tokio::time::Instant::now();
+    loop {
+        if stream.write_all(REQUEST).await.is_err() {
+            break;
+        }
+        match stream.read(&mut buf).await {
+            Ok(n) if n > 0 => tokio::time::sleep(Duration::from_millis(100)).await,
+            _ => break,
+        }
+    }
+
+    assert!(start.elapsed() >= Duration::from_millis(900));
+    assert_eq!(handle.stats().lifetime_expirations(), 1);
+    assert_eq!(handle.stats().idle_timeouts(), 0);
+    stop(handle).await;
+}
+
+#[tokio::test]
+async fn test_stalled_tls_handshake_is_closed() {
+    let dir = std::env::temp_dir().join(format!("grafton-timeout-{}", std::process::id()));
+    let mut config = Config::default();
+    config.website.timeouts.tls_handshake_secs = Some(1);
+    config.website.listeners = vec![ListenerConfig {
+        port: 0,
+        tls: true,
+        ..Default::default()
+    }];
+    let ssl = &mut config.website.bind_ssl_config;
+    ssl.enabled = true;
+    ssl.generate_self_signed = true;
+    ssl.cert_path = dir.join("cert.pem").to_string_lossy().into();
+    ssl.key_path = dir.join("key.pem").to_string_lossy().into();
+    let (handle, addr) = start(config, router());
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    wait_for_close(&mut stream).await;
+
+    assert_eq!(handle.stats().tls_handshake_timeouts(), 1);
+    stop(handle).await;
+    std::fs::remove_dir_all(dir).unwrap();
+}